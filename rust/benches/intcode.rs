@@ -0,0 +1,162 @@
+//! Compares the interpreter with and without its decode cache, and the compiler, against a
+//! baseline interpreter that decodes like the interpreter once did. Run with `cargo bench`.
+
+use adventofcode2019::intcode::assembler::assemble;
+use adventofcode2019::intcode::compiler::compile;
+use adventofcode2019::intcode::{Computer, Memory};
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 10;
+
+/// Counts down from its input, adding the counter to a running total.
+const COUNTDOWN: &str = "
+        IN -> [counter]
+loop:   ADD [total], [counter] -> [total]
+        ADD [counter], -1 -> [counter]
+        JNZ [counter], loop
+        OUT [total]
+        HLT
+counter: DATA 0
+total:   DATA 0
+";
+
+/// Counts down from its input like `COUNTDOWN`, but on every iteration rewrites its addition to
+/// switch the second parameter between position and relative mode, which defeats the decode
+/// cache. The relative base stays at zero, so both forms add the same value.
+const SELF_MODIFYING: &str = "
+        IN -> [counter]
+loop:   ADD [counter], -1 -> [counter]
+        MUL [patch], -1 -> [scratch]
+        ADD [scratch], 2002 -> [patch]
+patch:  ADD [total], [counter] -> [total]
+        JNZ [counter], loop
+        OUT [total]
+        HLT
+counter: DATA 0
+total:   DATA 0
+scratch: DATA 0
+";
+
+/// The decoder the interpreter used before it stopped allocating: the parameter modes of every
+/// instruction are collected into a `Vec`. Supports just enough to run the programs above.
+fn execute_allocating(mut memory: Memory, input: i64) -> Option<i64> {
+    let (mut pointer, mut relative_base, mut output) = (0, 0, None);
+    loop {
+        let word = memory.get(pointer);
+        let modes: Vec<i64> = [100, 1000, 10000]
+            .iter()
+            .map(|divisor| word / divisor % 10)
+            .collect();
+        let address = |memory: &Memory, index: usize| -> usize {
+            let parameter = memory.get(pointer + 1 + index);
+            match modes[index] {
+                0 => parameter as usize,
+                2 => (relative_base + parameter) as usize,
+                mode => panic!("Cannot write in mode {}", mode),
+            }
+        };
+        let read = |memory: &Memory, index: usize| match modes[index] {
+            1 => memory.get(pointer + 1 + index),
+            _ => memory.get(address(memory, index)),
+        };
+        match word % 100 {
+            opcode @ 1 | opcode @ 2 | opcode @ 7 | opcode @ 8 => {
+                let (left, right) = (read(&memory, 0), read(&memory, 1));
+                let value = match opcode {
+                    1 => left + right,
+                    2 => left * right,
+                    7 => (left < right) as i64,
+                    _ => (left == right) as i64,
+                };
+                memory.set(address(&memory, 2), value);
+                pointer += 4;
+            }
+            3 => {
+                memory.set(address(&memory, 0), input);
+                pointer += 2;
+            }
+            4 => {
+                output = Some(read(&memory, 0));
+                pointer += 2;
+            }
+            opcode @ 5 | opcode @ 6 => {
+                if (read(&memory, 0) != 0) == (opcode == 5) {
+                    pointer = read(&memory, 1) as usize;
+                } else {
+                    pointer += 3;
+                }
+            }
+            9 => {
+                relative_base += read(&memory, 0);
+                pointer += 2;
+            }
+            99 => return output,
+            opcode => panic!("Unknown opcode {}", opcode),
+        }
+    }
+}
+
+fn time_allocating(memory: &Memory, input: i64) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        execute_allocating(memory.clone(), input);
+        best = best.min(start.elapsed());
+    }
+
+    best
+}
+
+fn time(memory: &Memory, input: i64, cache: bool) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut computer = Computer::new(memory.clone());
+        computer.set_decode_cache(cache);
+        let start = Instant::now();
+        computer.execute(Some(input)).expect("Program failed");
+        best = best.min(start.elapsed());
+    }
+
+    best
+}
+
+fn time_compiled(memory: &Memory, input: i64) -> Duration {
+    let program = compile(memory);
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut computer = program.computer();
+        let start = Instant::now();
+        computer.execute(Some(input)).expect("Program failed");
+        best = best.min(start.elapsed());
+    }
+
+    best
+}
+
+fn bench(name: &str, source: &str, input: i64) {
+    let memory = assemble(source).expect("Could not assemble");
+    assert_eq!(
+        Computer::new(memory.clone()).execute(Some(input)),
+        Ok(execute_allocating(memory.clone(), input))
+    );
+    let allocating = time_allocating(&memory, input);
+    let uncached = time(&memory, input, false);
+    let cached = time(&memory, input, true);
+    let compiled = time_compiled(&memory, input);
+    println!(
+        "{:<16} allocating {:>10.2?}  uncached {:>10.2?}  cached {:>10.2?}  compiled {:>10.2?}  \
+         speedup {:.2}x / {:.2}x",
+        name,
+        allocating,
+        uncached,
+        cached,
+        compiled,
+        uncached.as_secs_f64() / cached.as_secs_f64(),
+        uncached.as_secs_f64() / compiled.as_secs_f64()
+    );
+}
+
+fn main() {
+    bench("countdown", COUNTDOWN, 1_000_000);
+    bench("self-modifying", SELF_MODIFYING, 1_000_000);
+}
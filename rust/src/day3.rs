@@ -38,7 +38,7 @@ impl Direction {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 struct Point {
     pub x: i32,
     pub y: i32,
@@ -50,6 +50,12 @@ impl Ord for Point {
     }
 }
 
+impl PartialOrd for Point {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Point {
     pub fn manhattan_distance(&self) -> i32 {
         self.x + self.y
@@ -71,7 +77,7 @@ impl Wire {
                     (0..distance)
                         .map(|_i| {
                             position.x += 1;
-                            position.clone()
+                            position
                         })
                         .collect()
                 }
@@ -80,7 +86,7 @@ impl Wire {
                     (0..distance)
                         .map(|_i| {
                             position.x -= 1;
-                            position.clone()
+                            position
                         })
                         .collect()
                 }
@@ -89,7 +95,7 @@ impl Wire {
                     (0..distance)
                         .map(|_i| {
                             position.y -= 1;
-                            position.clone()
+                            position
                         })
                         .collect()
                 }
@@ -98,7 +104,7 @@ impl Wire {
                     (0..distance)
                         .map(|_i| {
                             position.y += 1;
-                            position.clone()
+                            position
                         })
                         .collect()
                 }
@@ -118,10 +124,7 @@ impl Wire {
         let self_set: HashSet<Point> = self.0.clone().into_iter().collect();
         let other_set: HashSet<Point> = other.0.clone().into_iter().collect();
 
-        self_set
-            .intersection(&other_set)
-            .map(|point| *point)
-            .collect()
+        self_set.intersection(&other_set).copied().collect()
     }
 
     fn nearest_intersection(&self, other: &Wire) -> Point {
@@ -138,10 +141,7 @@ impl Wire {
             .0
             .iter()
             .position(|&point| point == *selected)
-            .expect(&format!(
-                "Point {:?} should exist on Wire {:?}",
-                selected, self
-            ))
+            .unwrap_or_else(|| panic!("Point {:?} should exist on Wire {:?}", selected, self))
     }
 
     pub fn total_steps_for_nearest_intersection(&self, other: &Wire) -> usize {
@@ -6,7 +6,7 @@ fn valid_password(password: &str) -> bool {
 }
 
 fn has_exactly_double_digits(password: &str) -> bool {
-    vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]
+    ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]
         .iter()
         .any(|digit| password.contains(&digit.repeat(2)) && !password.contains(&digit.repeat(3)))
 }
@@ -79,7 +79,7 @@ impl ExactSizeIterator for PasswordRange {
 impl PasswordRange {
     pub fn valid_passwords(&self) -> Vec<String> {
         self.map(|password_num| password_num.to_string())
-            .filter(|password| valid_password(&password))
+            .filter(|password| valid_password(password))
             .collect()
     }
 }
@@ -60,19 +60,19 @@ impl Object {
                 let x_position = orbit_list
                     .iter()
                     .position(|item| item.as_str() == **x)
-                    .expect(format!("Cannot find {}", x).as_str())
+                    .unwrap_or_else(|| panic!("Cannot find {}", x))
                     + other_orbit_list
                         .iter()
                         .position(|item| item.as_str() == **x)
-                        .expect(format!("Cannot find {}", x).as_str());
+                        .unwrap_or_else(|| panic!("Cannot find {}", x));
                 let y_position = orbit_list
                     .iter()
                     .position(|item| item.as_str() == **y)
-                    .expect(format!("Cannot find {}", y).as_str())
+                    .unwrap_or_else(|| panic!("Cannot find {}", y))
                     + other_orbit_list
                         .iter()
                         .position(|item| item.as_str() == **y)
-                        .expect(format!("Cannot find {}", y).as_str());
+                        .unwrap_or_else(|| panic!("Cannot find {}", y));
 
                 x_position.cmp(&y_position)
             })
@@ -135,11 +135,11 @@ pub fn orbital_transfers(mapping: &str, object_name_1: &str, object_name_2: &str
     let map = orbit_map_from_string(mapping);
     let object_1 = map
         .get(object_name_1)
-        .expect(format!("Cannot find {}", object_name_1).as_str())
+        .unwrap_or_else(|| panic!("Cannot find {}", object_name_1))
         .borrow();
     let object_2 = map
         .get(object_name_2)
-        .expect(format!("Cannot find {}", object_name_2).as_str())
+        .unwrap_or_else(|| panic!("Cannot find {}", object_name_2))
         .borrow();
 
     object_1.orbit_distance(&object_2, &map) - 2
@@ -149,7 +149,7 @@ pub fn orbital_transfers(mapping: &str, object_name_1: &str, object_name_2: &str
 mod tests {
     use super::{orbit_count_checksum, orbit_map_from_string, orbital_transfers};
 
-    const MAPPING: &'static str = "COM)B
+    const MAPPING: &str = "COM)B
 B)C
 C)D
 D)E
@@ -160,7 +160,7 @@ D)I
 E)J
 J)K
 K)L";
-    const SANTA_MAPPING: &'static str = "COM)B
+    const SANTA_MAPPING: &str = "COM)B
 B)C
 C)D
 D)E
@@ -1,270 +1,948 @@
-use std::{io, str};
+pub mod amplifier;
+pub mod analysis;
+pub mod ascii;
+pub mod assembler;
+mod asynchronous;
+pub mod compiler;
+pub mod conformance;
+pub mod debugger;
+mod device;
+pub mod disassembler;
+#[cfg(test)]
+mod fuzz;
+mod instruction_set;
+mod limits;
+pub mod loader;
+mod memory;
+pub mod network;
+pub mod profiler;
+pub mod screen;
+mod snapshot;
+pub mod trace;
+
+pub use device::{FnInput, FnOutput, Input, IterInput, Output};
+pub use instruction_set::{Builder, Effect, InstructionSet, Opcode, Operands};
+pub use memory::Memory;
+pub use snapshot::Snapshot;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+use std::{error, fmt, io};
+use trace::{TraceEvent, Tracer};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        opcode: i64,
+        address: usize,
+    },
+    /// The instruction at `address` has a parameter mode that is unknown or cannot be used for
+    /// that parameter, such as writing to an immediate mode parameter.
+    InvalidMode {
+        mode: i64,
+        address: usize,
+    },
+    /// The instruction at `pointer` resolved a parameter to a negative address.
+    NegativeAddress {
+        address: i64,
+        pointer: usize,
+    },
+    /// The instruction pointer moved outside of the program.
+    PointerOutOfBounds {
+        pointer: i64,
+    },
+    /// The input instruction at `address` ran out of input values.
+    MissingInput {
+        address: usize,
+    },
+    /// The output sink of `execute_async` did not accept a value, with the pointer at
+    /// `address`.
+    OutputFailed {
+        address: usize,
+    },
+    /// A program could not be parsed. `offset` is the byte offset of the offending value, and
+    /// `line` and `column` count from 1, with columns in bytes.
+    Parse {
+        offset: usize,
+        line: usize,
+        column: usize,
+        value: String,
+    },
+    /// An arithmetic instruction produced a value that does not fit in 64 bits.
+    Overflow {
+        address: usize,
+    },
+    /// The program executed as many instructions as `Computer::set_instruction_limit` allows.
+    InstructionLimitExceeded {
+        limit: u64,
+    },
+    /// The deadline from `Computer::set_deadline` passed after `instructions` instructions.
+    DeadlineExceeded {
+        instructions: u64,
+    },
+    /// The program returned to an earlier state without any input or output, so it can never
+    /// halt. `pointer` is the address of the repeated state.
+    InfiniteLoop {
+        pointer: usize,
+    },
+}
 
-type Memory = Vec<i32>;
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode { opcode, address } => {
+                write!(f, "Unknown opcode {} at address {}", opcode, address)
+            }
+            IntcodeError::InvalidMode { mode, address } => {
+                write!(f, "Invalid parameter mode {} at address {}", mode, address)
+            }
+            IntcodeError::NegativeAddress { address, pointer } => write!(
+                f,
+                "Negative address {} used by the instruction at address {}",
+                address, pointer
+            ),
+            IntcodeError::PointerOutOfBounds { pointer } => {
+                write!(f, "Instruction pointer {} is out of bounds", pointer)
+            }
+            IntcodeError::MissingInput { address } => write!(
+                f,
+                "Input instruction at address {} has no input available",
+                address
+            ),
+            IntcodeError::OutputFailed { address } => write!(
+                f,
+                "Output sink failed with the pointer at address {}",
+                address
+            ),
+            IntcodeError::Parse {
+                line,
+                column,
+                value,
+                ..
+            } => write!(
+                f,
+                "Cannot parse {:?} at line {}, column {}",
+                value, line, column
+            ),
+            IntcodeError::Overflow { address } => {
+                write!(f, "Arithmetic overflow at address {}", address)
+            }
+            IntcodeError::InstructionLimitExceeded { limit } => {
+                write!(f, "Exceeded the limit of {} instructions", limit)
+            }
+            IntcodeError::DeadlineExceeded { instructions } => write!(
+                f,
+                "Exceeded the deadline after {} instructions",
+                instructions
+            ),
+            IntcodeError::InfiniteLoop { pointer } => write!(
+                f,
+                "Infinite loop: the state at address {} repeated without input or output",
+                pointer
+            ),
+        }
+    }
+}
 
-#[derive(Debug)]
+impl error::Error for IntcodeError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Mode {
     Position,
     Immediate,
+    Relative,
 }
 
 impl Mode {
-    pub fn from_code(code: i32) -> Self {
+    pub fn code(&self) -> i64 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+
+    /// Parses a single mode digit. Unknown codes are returned as the error.
+    pub fn from_code(code: i64) -> Result<Self, i64> {
         match code {
-            0 => Mode::Position,
-            1 => Mode::Immediate,
-            _ => panic!(format!("Cannot parse mode {}", code)),
+            0 => Ok(Mode::Position),
+            1 => Ok(Mode::Immediate),
+            2 => Ok(Mode::Relative),
+            _ => Err(code),
         }
     }
-    pub fn modes_from_instruction(instruction: i32) -> Vec<Self> {
-        let mut modes = vec![Mode::Position, Mode::Position, Mode::Position];
+
+    /// Parses the parameter modes of an instruction. The first unknown mode code is returned
+    /// as the error.
+    pub fn modes_from_instruction(instruction: i64) -> Result<Modes, i64> {
+        let mut modes = [Mode::Position; 3];
         let mut mode_codes = instruction / 100;
         let mut index = 0;
         while mode_codes > 0 {
-            modes[index] = Self::from_code(mode_codes % 10);
+            if index == modes.len() {
+                return Err(mode_codes);
+            }
+            modes[index] = Self::from_code(mode_codes % 10)?;
             mode_codes /= 10;
             index += 1;
         }
 
-        modes
+        Ok(modes)
     }
 }
 
-#[derive(Debug)]
-enum Operation {
-    Add,
-    Multiply,
-    LessThan,
-    Equals,
+type Modes = [Mode; 3];
+
+/// The decoded form of an instruction word.
+#[derive(Clone, Copy, Debug)]
+struct Instruction {
+    /// The word that was decoded, so that cached instructions can be checked against memory.
+    word: i64,
+    opcode: i64,
+    modes: Modes,
+}
+
+impl Instruction {
+    fn decode(word: i64) -> Result<Self, i64> {
+        Ok(Self {
+            word,
+            opcode: word % 100,
+            modes: Mode::modes_from_instruction(word)?,
+        })
+    }
+}
+
+/// What a running `Computer` needs from its caller after executing an instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+    /// The instruction completed without needing anything from the caller.
+    Running,
+    /// An input instruction is waiting for a value. The pointer stays on that instruction, so
+    /// resuming after `push_input` retries it.
+    NeedsInput,
+    /// An output instruction produced a value.
+    Output(i64),
+    Halted,
 }
 
 pub struct Computer {
     pub memory: Memory,
     pointer: usize,
-    input: Option<i32>,
-    output: Option<i32>,
+    relative_base: i64,
+    inputs: VecDeque<i64>,
+    outputs: VecDeque<i64>,
+    input: Option<Box<dyn Input + Send>>,
+    output: Option<Box<dyn Output + Send>>,
+    last_write: Option<(usize, i64)>,
+    tracer: Option<Box<dyn Tracer + Send>>,
+    /// Decoded instructions indexed by address, or `None` when the decode cache is disabled.
+    decode_cache: Option<Vec<Option<Instruction>>>,
+    instruction_set: Arc<InstructionSet>,
+    instruction_limit: Option<u64>,
+    deadline: Option<Instant>,
+    /// The number of instructions executed, for the instruction limit and deadline.
+    instructions: u64,
+    loop_detector: Option<limits::LoopDetector>,
 }
 
 impl Computer {
-    pub fn new(memory: Memory) -> Self {
+    pub fn new<M: Into<Memory>>(memory: M) -> Self {
         Self {
-            memory,
+            memory: memory.into(),
             pointer: 0,
+            relative_base: 0,
+            inputs: VecDeque::new(),
+            outputs: VecDeque::new(),
             input: None,
             output: None,
+            last_write: None,
+            tracer: None,
+            decode_cache: Some(vec![]),
+            instruction_set: instruction_set::core(),
+            instruction_limit: None,
+            deadline: None,
+            instructions: 0,
+            loop_detector: None,
         }
     }
 
-    pub fn execute(&mut self, input: Option<i32>) -> Option<i32> {
-        self.input = input;
-        self.pointer = 0;
-        while self.pointer < self.memory.len() {
-            let instruction = self.memory[self.pointer];
-            let modes = Mode::modes_from_instruction(instruction);
-            let opcode = instruction % 100;
-            if self.execute_opcode(opcode, modes).is_err() {
-                break;
+    /// Starts configuring a computer, for example to add opcodes to its instruction set.
+    pub fn builder<M: Into<Memory>>(memory: M) -> Builder {
+        Builder::new(Self::new(memory))
+    }
+
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.instruction_set
+    }
+
+    /// Reads input from `input` once the values queued with `push_input` run out.
+    pub fn set_input<I: Input + Send + 'static>(&mut self, input: I) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// Sends output values to `output` instead of the internal output queue.
+    pub fn set_output<O: Output + Send + 'static>(&mut self, output: O) {
+        self.output = Some(Box::new(output));
+    }
+
+    /// Reports every executed instruction to `tracer`. Without a tracer, no events are built.
+    pub fn set_tracer<T: Tracer + Send + 'static>(&mut self, tracer: T) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer + Send>> {
+        self.tracer.take()
+    }
+
+    /// Turns the cache of decoded instructions on or off. It is on by default. A cached
+    /// instruction is only reused while its memory word is unchanged, so programs that modify
+    /// themselves, or are patched through `memory`, are decoded again.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = if enabled { Some(vec![]) } else { None };
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /// The address and value written by the most recent `step`, if it wrote to memory.
+    pub fn last_write(&self) -> Option<(usize, i64)> {
+        self.last_write
+    }
+
+    /// Queues a value for the next input instruction.
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(value);
+    }
+
+    /// Removes and returns the output values that were not sent to an output sink.
+    pub fn take_outputs(&mut self) -> Vec<i64> {
+        self.outputs.drain(..).collect()
+    }
+
+    /// Runs the program from the start until it halts, returning the last output.
+    pub fn execute(&mut self, input: Option<i64>) -> Result<Option<i64>, IntcodeError> {
+        self.execute_with(input, Self::step)
+    }
+
+    /// Continues from the current pointer until the program halts or runs out of input. Output
+    /// values go to the output sink, or to the output queue if there is none.
+    pub fn run(&mut self) -> Result<State, IntcodeError> {
+        self.run_with(Self::step)
+    }
+
+    /// Continues from the current pointer until the program needs input, produces output, or
+    /// halts.
+    pub fn resume(&mut self) -> Result<State, IntcodeError> {
+        self.resume_with(Self::step)
+    }
+
+    /// `execute`, but executing each instruction with `step`, so that `CompiledComputer` can
+    /// share the run loops.
+    fn execute_with<F>(
+        &mut self,
+        input: Option<i64>,
+        mut step: F,
+    ) -> Result<Option<i64>, IntcodeError>
+    where
+        F: FnMut(&mut Self) -> Result<State, IntcodeError>,
+    {
+        self.restart(input);
+        let mut last_output = None;
+        loop {
+            match self.resume_with(&mut step)? {
+                State::NeedsInput => {
+                    return Err(IntcodeError::MissingInput {
+                        address: self.pointer,
+                    })
+                }
+                State::Output(value) => {
+                    last_output = Some(value);
+                    self.emit(value);
+                }
+                State::Halted => return Ok(last_output),
+                State::Running => unreachable!(),
             }
         }
+    }
 
-        self.output
-    }
-
-    fn execute_opcode(&mut self, opcode: i32, modes: Vec<Mode>) -> Result<(), ()> {
-        match opcode {
-            1 => self.execute_operation(Operation::Add, modes),
-            2 => self.execute_operation(Operation::Multiply, modes),
-            3 => self.set_input(),
-            4 => self.set_output(modes),
-            5 => self.set_pointer(true, modes),
-            6 => self.set_pointer(false, modes),
-            7 => self.execute_operation(Operation::LessThan, modes),
-            8 => self.execute_operation(Operation::Equals, modes),
-            99 => return Err(()),
-            _ => panic!("Unknown opcode: {:?}", opcode),
+    fn run_with<F>(&mut self, mut step: F) -> Result<State, IntcodeError>
+    where
+        F: FnMut(&mut Self) -> Result<State, IntcodeError>,
+    {
+        loop {
+            match self.resume_with(&mut step)? {
+                State::Output(value) => self.emit(value),
+                state => return Ok(state),
+            }
         }
-
-        Ok(())
     }
 
-    fn execute_operation(&mut self, operation: Operation, modes: Vec<Mode>) {
-        let output_address = self.memory[self.pointer + 3] as usize;
+    fn resume_with<F>(&mut self, mut step: F) -> Result<State, IntcodeError>
+    where
+        F: FnMut(&mut Self) -> Result<State, IntcodeError>,
+    {
+        loop {
+            match step(self)? {
+                State::Running => continue,
+                state => return Ok(state),
+            }
+        }
+    }
 
-        let operand1 = self.get_value(1, &modes[0]);
-        let operand2 = self.get_value(2, &modes[1]);
+    /// Moves back to the start of the program for a new run, queueing `input` in place of any
+    /// leftover input. Memory is not reset.
+    fn restart(&mut self, input: Option<i64>) {
+        self.pointer = 0;
+        self.relative_base = 0;
+        self.inputs.clear();
+        self.inputs.extend(input);
+        self.reset_limits();
+    }
 
-        let result = match operation {
-            Operation::Add => operand1 + operand2,
-            Operation::Multiply => operand1 * operand2,
-            Operation::LessThan => {
-                if operand1 < operand2 {
-                    1
-                } else {
-                    0
+    /// Executes the instruction at the current pointer.
+    pub fn step(&mut self) -> Result<State, IntcodeError> {
+        self.last_write = None;
+        self.check_limits()?;
+        if self.pointer >= self.memory.extent() {
+            return Err(IntcodeError::PointerOutOfBounds {
+                pointer: self.pointer as i64,
+            });
+        }
+        let Instruction { opcode, modes, .. } = self.decode()?;
+        let dispatch =
+            self.instruction_set
+                .dispatch(opcode)
+                .ok_or(IntcodeError::UnknownOpcode {
+                    opcode,
+                    address: self.pointer,
+                })?;
+        let event = match self.tracer {
+            Some(_) => self.trace_event(opcode, &modes),
+            None => None,
+        };
+        let effect = match dispatch.core {
+            Some(core) => core.execute(&mut Operands::new(self, modes))?,
+            // Registered opcodes are closures, which can only be called while the computer is
+            // borrowed mutably by holding a reference to the instruction set of their own.
+            None => {
+                let instruction_set = Arc::clone(&self.instruction_set);
+                let definition = instruction_set.get(opcode).expect("Opcode is registered");
+                definition.execute(&mut Operands::new(self, modes))?
+            }
+        };
+        let mut jump = None;
+        let state = match effect {
+            Effect::Next => {
+                self.pointer += 1 + dispatch.arity;
+                State::Running
+            }
+            Effect::Jump(target) => {
+                if target < 0 {
+                    return Err(IntcodeError::PointerOutOfBounds { pointer: target });
                 }
+                self.pointer = target as usize;
+                jump = Some(self.pointer);
+                self.check_loop()?;
+                State::Running
             }
-            Operation::Equals => {
-                if operand1 == operand2 {
-                    1
-                } else {
-                    0
+            Effect::Output(value) => {
+                self.pointer += 1 + dispatch.arity;
+                if let Some(detector) = self.loop_detector.as_mut() {
+                    detector.reset();
                 }
+                State::Output(value)
             }
+            Effect::NeedsInput => State::NeedsInput,
+            Effect::Halt => State::Halted,
         };
+        if state != State::NeedsInput {
+            self.instructions += 1;
+        }
+        if let (Some(mut event), Some(tracer)) = (event, self.tracer.as_mut()) {
+            if state != State::NeedsInput {
+                event.write = self.last_write;
+                event.jump = jump;
+                tracer.trace(&event);
+            }
+        }
+
+        Ok(state)
+    }
 
-        self.memory[output_address] = result;
-        self.pointer += 4;
+    fn decode(&mut self) -> Result<Instruction, IntcodeError> {
+        let pointer = self.pointer;
+        let word = self.memory.get(pointer);
+        if let Some(Some(instruction)) = self
+            .decode_cache
+            .as_ref()
+            .and_then(|cache| cache.get(pointer))
+        {
+            if instruction.word == word {
+                return Ok(*instruction);
+            }
+        }
+
+        let instruction = Instruction::decode(word).map_err(|mode| IntcodeError::InvalidMode {
+            mode,
+            address: pointer,
+        })?;
+        if let Some(cache) = self.decode_cache.as_mut() {
+            if pointer >= cache.len() {
+                cache.resize(pointer + 1, None);
+            }
+            cache[pointer] = Some(instruction);
+        }
+
+        Ok(instruction)
     }
 
-    fn set_input(&mut self) {
-        if let Some(input_value) = self.input {
-            let input_address = self.memory[self.pointer + 1] as usize;
-            self.memory[input_address] = input_value;
-        } else {
-            panic!("ERROR! Input opcode specified, but no input specified");
+    /// Resolves the operands of the instruction at the pointer before it executes.
+    fn trace_event(&self, opcode: i64, modes: &Modes) -> Option<TraceEvent> {
+        let definition = self.instruction_set.get(opcode)?;
+        let modes: Vec<Mode> = (0..definition.arity())
+            .map(|index| modes.get(index).copied().unwrap_or(Mode::Position))
+            .collect();
+        let mut operands = Vec::with_capacity(modes.len());
+        for (index, mode) in modes.iter().enumerate() {
+            let operand = if definition.writes && index == definition.reads {
+                self.get_address(index + 1, mode).ok()? as i64
+            } else {
+                self.get_value(index + 1, mode).ok()?
+            };
+            operands.push(operand);
         }
-        self.pointer += 2;
+
+        Some(TraceEvent {
+            pointer: self.pointer,
+            opcode: definition.opcode,
+            modes: modes.iter().map(Mode::code).collect(),
+            operands,
+            write: None,
+            jump: None,
+        })
     }
 
-    fn set_output(&mut self, modes: Vec<Mode>) {
-        self.output = Some(self.get_value(1, &modes[0]));
-        self.pointer += 2;
+    /// Takes the next queued input value, or reads one from the input source.
+    fn next_input(&mut self) -> Option<i64> {
+        let value = match self.inputs.pop_front() {
+            Some(value) => Some(value),
+            None => self.input.as_mut().and_then(|input| input.read()),
+        };
+        if let (Some(_), Some(detector)) = (value, self.loop_detector.as_mut()) {
+            detector.reset();
+        }
+        value
     }
 
-    fn set_pointer(&mut self, test: bool, modes: Vec<Mode>) {
-        let value = self.get_value(1, &modes[0]);
-        if (value != 0) == test {
-            self.pointer = self.get_value(2, &modes[1]) as usize;
-        } else {
-            self.pointer += 3;
+    fn write(&mut self, address: usize, value: i64) {
+        if let Some(detector) = self.loop_detector.as_mut() {
+            detector.update(address, self.memory.get(address), value);
         }
+        self.memory.set(address, value);
+        self.last_write = Some((address, value));
     }
 
-    fn get_value(&self, offset: usize, mode: &Mode) -> i32 {
+    fn emit(&mut self, value: i64) {
+        match self.output.as_mut() {
+            Some(output) => output.write(value),
+            None => self.outputs.push_back(value),
+        }
+    }
+
+    fn get_address(&self, offset: usize, mode: &Mode) -> Result<usize, IntcodeError> {
+        let parameter = self.memory.get(self.pointer + offset);
+        let address = match mode {
+            Mode::Position => parameter,
+            Mode::Immediate => {
+                return Err(IntcodeError::InvalidMode {
+                    mode: 1,
+                    address: self.pointer,
+                })
+            }
+            Mode::Relative => self
+                .relative_base
+                .checked_add(parameter)
+                .ok_or_else(|| self.overflow())?,
+        };
+        if address < 0 {
+            return Err(IntcodeError::NegativeAddress {
+                address,
+                pointer: self.pointer,
+            });
+        }
+
+        Ok(address as usize)
+    }
+
+    fn get_value(&self, offset: usize, mode: &Mode) -> Result<i64, IntcodeError> {
         match mode {
-            Mode::Position => self.memory[self.memory[self.pointer + offset] as usize],
-            Mode::Immediate => self.memory[self.pointer + offset],
+            Mode::Immediate => Ok(self.memory.get(self.pointer + offset)),
+            _ => Ok(self.memory.get(self.get_address(offset, mode)?)),
         }
     }
-}
 
-pub fn memory_from_io<T: io::BufRead>(input: T) -> io::Result<Memory> {
-    let mut memory = vec![];
-    for item in input.split(b',') {
-        let bytes = item?;
-        let serialized = str::from_utf8(&bytes)
-            .expect("Could not create string")
-            .trim();
-        memory.push(serialized.parse().expect("Could not parse number"));
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow {
+            address: self.pointer,
+        }
     }
+}
 
-    Ok(memory)
+/// Loads a program with `loader::load`, which accepts text, binary and gzip-compressed
+/// programs. Values that cannot be parsed are reported as an `io::ErrorKind::InvalidData` error
+/// wrapping an `IntcodeError::Parse`.
+pub fn memory_from_io<T: io::BufRead>(input: T) -> io::Result<Memory> {
+    loader::load(input)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Computer, Memory};
+    use super::assembler::assemble;
+    use super::compiler::compile;
+    use super::{memory_from_io, Computer, FnInput, FnOutput, IntcodeError, IterInput, State};
+    use std::collections::VecDeque;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
 
     #[test]
-    fn test_execute() {
+    fn test_relative_mode() {
+        assert_intcode_output(vec![109, 5, 204, 1, 99, 0, 42], None, 42);
+        assert_intcode_output(vec![109, 4, 209, 3, 204, -8, 99, 5], None, 4);
         assert_intcode_executed(
-            vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
-            vec![3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50],
-            None,
+            vec![109, 7, 203, -1, 99, 0, 0, 0],
+            vec![109, 7, 203, -1, 99, 0, 12, 0],
+            Some(12),
         );
+        assert_intcode_output(vec![109, 10, 21101, 3, 4, 0, 4, 10, 99, 0, 0], None, 7);
+    }
 
-        assert_intcode_executed(vec![1, 0, 0, 0, 99], vec![2, 0, 0, 0, 99], None);
+    #[test]
+    fn test_large_numbers() {
+        assert_intcode_output(
+            vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0],
+            None,
+            1219070632396864,
+        );
+        assert_intcode_output(vec![104, 1125899906842624, 99], None, 1125899906842624);
+    }
 
-        assert_intcode_executed(vec![2, 3, 0, 3, 99], vec![2, 3, 0, 6, 99], None);
+    #[test]
+    fn test_overflow() {
+        let mut computer = Computer::new(vec![1102, i64::MAX, 2, 0, 99]);
+        assert_eq!(
+            Err(IntcodeError::Overflow { address: 0 }),
+            computer.execute(None)
+        );
 
-        assert_intcode_executed(vec![2, 4, 4, 5, 99, 0], vec![2, 4, 4, 5, 99, 9801], None);
+        let mut computer = Computer::new(vec![4, 0, 1101, i64::MIN, -1, 0, 99]);
+        assert_eq!(
+            Err(IntcodeError::Overflow { address: 2 }),
+            computer.execute(None)
+        );
+    }
 
+    #[test]
+    fn test_memory_beyond_program() {
+        assert_intcode_output(vec![4, 100, 99], None, 0);
         assert_intcode_executed(
-            vec![1, 1, 1, 4, 99, 5, 6, 0, 99],
-            vec![30, 1, 1, 4, 2, 5, 6, 0, 99],
+            vec![1101, 2, 3, 7, 99],
+            vec![1101, 2, 3, 7, 99, 0, 0, 5],
             None,
         );
+
+        let mut computer = Computer::new(vec![109, 1_000_000_000, 21101, 4, 5, 7, 204, 7, 99]);
+        assert_eq!(Ok(Some(9)), computer.execute(None));
+        assert_eq!(9, computer.memory.get(1_000_000_007));
+        assert_eq!(9, computer.memory.len());
     }
 
     #[test]
-    fn test_execute_input() {
-        assert_intcode_executed(vec![3, 3, 99, 10], vec![3, 3, 99, 40], Some(40));
+    fn test_pointer_beyond_dense_region() {
+        let end = (1 << 20) + 10;
+        let mut words = vec![0; end];
+        words[..3].copy_from_slice(&[1105, 1, end as i64 - 1]);
+        words[end - 1] = 99;
+        assert_eq!(Ok(None), Computer::new(words).execute(None));
+
+        let mut computer = Computer::new(vec![1101, 99, 0, 2_000_000, 1105, 1, 2_000_000]);
+        assert_eq!(Ok(None), computer.execute(None));
+        assert_eq!(2_000_000, computer.pointer());
     }
 
     #[test]
-    fn test_execute_output() {
-        assert_intcode_output(vec![4, 0, 4, 7, 4, 3, 99, 12], None, 7);
+    fn test_resume() {
+        let mut computer = Computer::new(vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99, 0, 0, 0]);
+        assert_eq!(Ok(State::NeedsInput), computer.resume());
+        assert_eq!(0, computer.pointer());
+        computer.push_input(3);
+        assert_eq!(Ok(State::NeedsInput), computer.resume());
+        assert_eq!(2, computer.pointer());
+        computer.push_input(4);
+        assert_eq!(Ok(State::Output(7)), computer.resume());
+        assert_eq!(Ok(State::Halted), computer.resume());
+        assert_eq!(Ok(State::Halted), computer.resume());
+    }
 
-        let memory = vec![
-            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
-            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
-            20, 1105, 1, 46, 98, 99,
+    #[test]
+    fn test_step() {
+        let mut computer = Computer::new(vec![109, 3, 204, 1, 99]);
+        assert_eq!(Ok(State::Running), computer.step());
+        assert_eq!(3, computer.relative_base());
+        assert_eq!(Ok(State::Output(99)), computer.step());
+        assert_eq!(4, computer.pointer());
+        assert_eq!(Ok(State::Halted), computer.step());
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
         ];
-        assert_intcode_output(memory.clone(), Some(4), 999);
-        assert_intcode_output(memory.clone(), Some(8), 1000);
-        assert_intcode_output(memory.clone(), Some(12), 1001);
+        let mut amplifiers: Vec<Computer> = [9, 8, 7, 6, 5]
+            .iter()
+            .map(|&phase| {
+                let mut computer = Computer::new(program.clone());
+                computer.push_input(phase);
+                computer
+            })
+            .collect();
+
+        let mut signal = 0;
+        'feedback: loop {
+            for amplifier in amplifiers.iter_mut() {
+                amplifier.push_input(signal);
+                match amplifier.resume() {
+                    Ok(State::Output(value)) => signal = value,
+                    Ok(State::Halted) => break 'feedback,
+                    state => panic!("Unexpected state {:?}", state),
+                }
+            }
+        }
+        assert_eq!(139629729, signal);
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        let memory = assemble(
+            "
+            patch:  OUT 1
+                    JNZ [done], finish
+                    ADD 0, 1 -> [done]
+                    ADD 99, 0 -> [patch]
+                    JNZ 1, patch
+            finish: OUT 2
+                    HLT
+            done:   DATA 0
+            ",
+        )
+        .expect("Could not assemble");
+        for &enabled in [true, false].iter() {
+            let mut computer = Computer::new(memory.clone());
+            computer.set_decode_cache(enabled);
+            assert_eq!(Ok(State::Halted), computer.run());
+            assert_eq!(vec![1], computer.take_outputs());
+        }
+
+        let mut computer = Computer::new(vec![104, 1, 1105, 1, 0]);
+        assert_eq!(Ok(State::Output(1)), computer.resume());
+        computer.memory.set(0, 99);
+        assert_eq!(Ok(State::Halted), computer.resume());
     }
 
     #[test]
-    fn test_execute_with_immediate_mode() {
-        assert_intcode_executed(vec![1002, 4, 3, 4, 33], vec![1002, 4, 3, 4, 99], None);
+    fn test_run_queues() {
+        let mut computer = Computer::new(vec![3, 0, 4, 0, 3, 0, 4, 0, 3, 0, 4, 0, 99]);
+        computer.push_input(1);
+        computer.push_input(2);
+        assert_eq!(Ok(State::NeedsInput), computer.run());
+        computer.push_input(3);
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![1, 2, 3], computer.take_outputs());
+        assert!(computer.take_outputs().is_empty());
     }
 
     #[test]
-    fn test_less_than() {
-        assert_intcode_output(vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], Some(1), 1);
-        assert_intcode_output(vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], Some(8), 0);
-        assert_intcode_output(vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], Some(9), 0);
+    fn test_input_sources() {
+        let program = vec![3, 0, 3, 1, 1, 0, 1, 0, 4, 0, 99];
+
+        let mut computer = Computer::new(program.clone());
+        computer.set_input(VecDeque::from(vec![2, 3]));
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![5], computer.take_outputs());
+
+        let mut computer = Computer::new(program.clone());
+        computer.set_input(IterInput(vec![4, 5].into_iter()));
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![9], computer.take_outputs());
+
+        let mut computer = Computer::new(program.clone());
+        let mut next = 10;
+        computer.set_input(FnInput(move || {
+            next += 1;
+            Some(next)
+        }));
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![23], computer.take_outputs());
+
+        let mut computer = Computer::new(program);
+        computer.push_input(1);
+        computer.set_input(IterInput(vec![6].into_iter()));
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![7], computer.take_outputs());
+    }
 
-        assert_intcode_output(vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], Some(1), 1);
-        assert_intcode_output(vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], Some(8), 0);
-        assert_intcode_output(vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], Some(9), 0);
+    #[test]
+    fn test_output_sinks() {
+        let collected = Arc::new(Mutex::new(vec![]));
+        let sink = Arc::clone(&collected);
+        let mut computer = Computer::new(vec![104, 1, 104, 2, 99]);
+        computer.set_output(FnOutput(move |value| sink.lock().unwrap().push(value)));
+        assert_eq!(Ok(Some(2)), computer.execute(None));
+        assert_eq!(vec![1, 2], *collected.lock().unwrap());
+        assert!(computer.take_outputs().is_empty());
+
+        let collected = Arc::new(Mutex::new(vec![]));
+        let mut computer = Computer::new(vec![104, 1, 104, 2, 99]);
+        computer.set_output(Arc::clone(&collected));
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![1, 2], *collected.lock().unwrap());
+        assert!(computer.take_outputs().is_empty());
     }
 
     #[test]
-    fn test_equals() {
-        assert_intcode_output(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], Some(1), 0);
-        assert_intcode_output(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], Some(8), 1);
-        assert_intcode_output(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], Some(9), 0);
+    fn test_channels() {
+        let program = vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0];
+        let (first_sender, mut receiver) = mpsc::channel();
+        let mut handles = vec![];
+        for _ in 0..3 {
+            let (sender, next_receiver) = mpsc::channel();
+            let mut computer = Computer::new(program.clone());
+            computer.set_input(receiver);
+            computer.set_output(sender);
+            handles.push(thread::spawn(move || computer.run()));
+            receiver = next_receiver;
+        }
 
-        assert_intcode_output(vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], Some(1), 0);
-        assert_intcode_output(vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], Some(8), 1);
-        assert_intcode_output(vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], Some(9), 0);
+        first_sender.send(10).unwrap();
+        assert_eq!(Ok(13), receiver.recv());
+        for handle in handles {
+            assert_eq!(Ok(State::Halted), handle.join().unwrap());
+        }
     }
 
     #[test]
-    fn test_jump() {
-        assert_intcode_output(
-            vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
-            Some(0),
-            0,
+    fn test_errors() {
+        assert_intcode_error(
+            vec![1, 0, 0, 0, 42, 99],
+            IntcodeError::UnknownOpcode {
+                opcode: 42,
+                address: 4,
+            },
         );
-        assert_intcode_output(
-            vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
-            Some(1),
-            1,
+        assert_intcode_error(
+            vec![301, 0, 0, 0, 99],
+            IntcodeError::InvalidMode {
+                mode: 3,
+                address: 0,
+            },
         );
-
-        assert_intcode_output(
-            vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
-            Some(0),
-            0,
+        assert_intcode_error(
+            vec![101001, 0, 0, 0, 99],
+            IntcodeError::InvalidMode {
+                mode: 1,
+                address: 0,
+            },
         );
-        assert_intcode_output(
-            vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
-            Some(1),
-            1,
+        assert_intcode_error(
+            vec![11101, 1, 1, 0, 99],
+            IntcodeError::InvalidMode {
+                mode: 1,
+                address: 0,
+            },
+        );
+        assert_intcode_error(
+            vec![4, -1, 99],
+            IntcodeError::NegativeAddress {
+                address: -1,
+                pointer: 0,
+            },
+        );
+        assert_intcode_error(
+            vec![109, -5, 22201, 1, 1, 1, 99],
+            IntcodeError::NegativeAddress {
+                address: -4,
+                pointer: 2,
+            },
+        );
+        assert_intcode_error(
+            vec![1105, 1, -3, 99],
+            IntcodeError::PointerOutOfBounds { pointer: -3 },
+        );
+        assert_intcode_error(
+            vec![1105, 1, 50, 99],
+            IntcodeError::PointerOutOfBounds { pointer: 50 },
+        );
+        assert_intcode_error(
+            vec![1101, 0, 0, 0],
+            IntcodeError::PointerOutOfBounds { pointer: 4 },
+        );
+        assert_intcode_error(
+            vec![3, 0, 3, 1, 99],
+            IntcodeError::MissingInput { address: 2 },
         );
     }
 
-    fn assert_intcode_executed(memory: Memory, expected: Memory, input: Option<i32>) {
-        let mut computer = Computer::new(memory);
-        computer.execute(input);
-        assert_eq!(expected.as_slice(), computer.memory.as_slice());
+    #[test]
+    fn test_memory_from_io() {
+        let memory = memory_from_io("1,9, 10,3\n".as_bytes()).expect("Could not parse");
+        assert_eq!(vec![1, 9, 10, 3], memory.to_vec());
+
+        let error = memory_from_io("1,9, x10,3".as_bytes()).expect_err("Should not parse");
+        assert_eq!(
+            Some(&IntcodeError::Parse {
+                offset: 5,
+                line: 1,
+                column: 6,
+                value: "x10".to_string()
+            }),
+            error
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<IntcodeError>())
+        );
+    }
+
+    // These helpers run every program through the interpreter and the compiler, which must
+    // behave exactly alike.
+    fn assert_intcode_error(memory: Vec<i64>, expected: IntcodeError) {
+        let mut computer = Computer::new(memory.clone());
+        assert_eq!(Err(expected.clone()), computer.execute(Some(1)));
+
+        let mut compiled = compile(&memory.into()).computer();
+        assert_eq!(Err(expected), compiled.execute(Some(1)));
     }
 
-    fn assert_intcode_output(memory: Memory, input: Option<i32>, expected: i32) {
-        let mut computer = Computer::new(memory);
-        assert_eq!(Some(expected), computer.execute(input));
+    fn assert_intcode_executed(memory: Vec<i64>, expected: Vec<i64>, input: Option<i64>) {
+        let mut computer = Computer::new(memory.clone());
+        computer.execute(input).expect("Program failed");
+        assert_eq!(expected, computer.memory.to_vec());
+
+        let mut compiled = compile(&memory.into()).computer();
+        compiled.execute(input).expect("Compiled program failed");
+        assert_eq!(expected, compiled.memory().to_vec());
+    }
+
+    fn assert_intcode_output(memory: Vec<i64>, input: Option<i64>, expected: i64) {
+        let mut computer = Computer::new(memory.clone());
+        assert_eq!(Ok(Some(expected)), computer.execute(input));
+
+        let mut compiled = compile(&memory.into()).computer();
+        assert_eq!(Ok(Some(expected)), compiled.execute(input));
     }
 }
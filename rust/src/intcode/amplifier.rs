@@ -0,0 +1,204 @@
+use super::{Computer, IntcodeError, Memory, State};
+
+/// How the amplifiers in a chain are connected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Wiring {
+    /// The signal passes through each amplifier once.
+    Series,
+    /// The output of the last amplifier feeds back into the first until they all halt.
+    Feedback,
+}
+
+/// A chain of machines running the same program, where each machine's outputs are the next
+/// machine's inputs.
+pub struct Amplifiers {
+    computers: Vec<Computer>,
+    wiring: Wiring,
+}
+
+impl Amplifiers {
+    /// Creates one machine per phase setting. Each machine reads its phase setting before any
+    /// signal.
+    pub fn new(program: &Memory, phases: &[i64], wiring: Wiring) -> Self {
+        let computers = phases
+            .iter()
+            .map(|phase| {
+                let mut computer = Computer::new(program.clone());
+                computer.push_input(*phase);
+                computer
+            })
+            .collect();
+
+        Self { computers, wiring }
+    }
+
+    /// Sends `signal` into the first machine and returns the last value the last machine
+    /// produced. Fails with `MissingInput` if every machine is waiting for input that will
+    /// never arrive.
+    pub fn run(&mut self, signal: i64) -> Result<Option<i64>, IntcodeError> {
+        let mut signals = vec![signal];
+        let mut last_signal = None;
+        loop {
+            let mut progressed = false;
+            let mut halted = true;
+            for computer in self.computers.iter_mut() {
+                progressed |= !signals.is_empty();
+                for value in signals.drain(..) {
+                    computer.push_input(value);
+                }
+                match computer.run()? {
+                    State::Halted => {}
+                    _ => halted = false,
+                }
+                signals = computer.take_outputs();
+            }
+            if let Some(value) = signals.last() {
+                last_signal = Some(*value);
+            }
+
+            match self.wiring {
+                Wiring::Series if !halted => return Err(self.missing_input()),
+                Wiring::Series => return Ok(last_signal),
+                Wiring::Feedback if halted => return Ok(last_signal),
+                Wiring::Feedback if !progressed && signals.is_empty() => {
+                    return Err(self.missing_input())
+                }
+                Wiring::Feedback => {}
+            }
+        }
+    }
+
+    fn missing_input(&self) -> IntcodeError {
+        let waiting = self
+            .computers
+            .iter()
+            .find(|computer| computer.memory.get(computer.pointer()) % 100 == 3)
+            .or_else(|| self.computers.first());
+        IntcodeError::MissingInput {
+            address: waiting.map_or(0, Computer::pointer),
+        }
+    }
+}
+
+/// Tries every ordering of `phases` and returns the highest final signal together with the
+/// phase settings that produced it.
+pub fn max_signal(
+    program: &Memory,
+    phases: &[i64],
+    wiring: Wiring,
+) -> Result<Option<(i64, Vec<i64>)>, IntcodeError> {
+    let mut best: Option<(i64, Vec<i64>)> = None;
+    for permutation in permutations(phases) {
+        let signal = Amplifiers::new(program, &permutation, wiring).run(0)?;
+        if let Some(signal) = signal {
+            let better = match &best {
+                Some((max, _)) => signal > *max,
+                None => true,
+            };
+            if better {
+                best = Some((signal, permutation));
+            }
+        }
+    }
+
+    Ok(best)
+}
+
+/// Every ordering of `items`, generated with Heap's algorithm.
+pub fn permutations(items: &[i64]) -> Vec<Vec<i64>> {
+    let mut items = items.to_vec();
+    let mut counters = vec![0; items.len()];
+    let mut result = vec![items.clone()];
+    let mut index = 1;
+    while index < items.len() {
+        if counters[index] < index {
+            let swap_with = if index % 2 == 0 { 0 } else { counters[index] };
+            items.swap(swap_with, index);
+            result.push(items.clone());
+            counters[index] += 1;
+            index = 1;
+        } else {
+            counters[index] = 0;
+            index += 1;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{max_signal, permutations, Amplifiers, Wiring};
+    use crate::intcode::{IntcodeError, Memory};
+
+    const FEEDBACK_PROGRAM: [i64; 57] = [
+        3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54, -5,
+        54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4, 53,
+        1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+    ];
+
+    #[test]
+    fn test_series() {
+        let program = Memory::from(vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ]);
+        assert_eq!(
+            Ok(Some(43210)),
+            Amplifiers::new(&program, &[4, 3, 2, 1, 0], Wiring::Series).run(0)
+        );
+
+        let program = Memory::from(vec![
+            3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23,
+            99, 0, 0,
+        ]);
+        assert_eq!(
+            Ok(Some((54321, vec![0, 1, 2, 3, 4]))),
+            max_signal(&program, &[0, 1, 2, 3, 4], Wiring::Series)
+        );
+    }
+
+    #[test]
+    fn test_feedback() {
+        let program = Memory::from(FEEDBACK_PROGRAM.to_vec());
+        assert_eq!(
+            Ok(Some(18216)),
+            Amplifiers::new(&program, &[9, 7, 8, 5, 6], Wiring::Feedback).run(0)
+        );
+        assert_eq!(
+            Ok(Some((18216, vec![9, 7, 8, 5, 6]))),
+            max_signal(&program, &[5, 6, 7, 8, 9], Wiring::Feedback)
+        );
+    }
+
+    #[test]
+    fn test_missing_input() {
+        let program = Memory::from(vec![3, 0, 3, 0, 3, 0, 99]);
+        assert_eq!(
+            Err(IntcodeError::MissingInput { address: 4 }),
+            Amplifiers::new(&program, &[1], Wiring::Series).run(0)
+        );
+
+        assert_eq!(
+            Err(IntcodeError::MissingInput { address: 4 }),
+            Amplifiers::new(&program, &[1, 2], Wiring::Feedback).run(0)
+        );
+    }
+
+    #[test]
+    fn test_permutations() {
+        let mut all = permutations(&[1, 2, 3]);
+        all.sort();
+        assert_eq!(
+            vec![
+                vec![1, 2, 3],
+                vec![1, 3, 2],
+                vec![2, 1, 3],
+                vec![2, 3, 1],
+                vec![3, 1, 2],
+                vec![3, 2, 1]
+            ],
+            all
+        );
+        assert_eq!(120, permutations(&[0, 1, 2, 3, 4]).len());
+    }
+}
@@ -0,0 +1,394 @@
+use super::disassembler::{decode, Line, Parameter};
+use super::instruction_set::Core;
+use super::{InstructionSet, Memory, Opcode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Edge {
+    /// Execution continues with the next instruction.
+    Next,
+    /// A jump is taken.
+    Jump,
+}
+
+/// A run of instructions that is only entered at the top and only left at the bottom.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<Line>,
+    /// The start addresses of the blocks that execution can continue with.
+    pub successors: Vec<(usize, Edge)>,
+    /// Whether the block ends with a jump to an address that is only known at runtime.
+    pub indirect: bool,
+}
+
+impl Block {
+    /// The address after the last instruction of the block.
+    pub fn end(&self) -> usize {
+        self.instructions
+            .last()
+            .map_or(self.start, |line| line.address() + line.size())
+    }
+}
+
+/// An instruction that writes to an address that holds code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SelfModification {
+    pub address: usize,
+    pub target: usize,
+}
+
+/// The control flow graph of the code that is reachable from address 0. Jump targets that come
+/// from memory or the relative base cannot be followed, so code that is only reached through
+/// them is reported as data. Registered opcodes are assumed to continue with the next
+/// instruction, since what their handlers do is not known.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub blocks: BTreeMap<usize, Block>,
+    /// Instructions whose position mode write parameter points into code.
+    pub self_modifications: Vec<SelfModification>,
+    /// Reachable addresses that do not hold a valid instruction, so executing them fails.
+    pub faults: Vec<usize>,
+    code: BTreeSet<usize>,
+    len: usize,
+}
+
+/// Where execution can go after an instruction.
+struct Flow {
+    next: bool,
+    jump: Option<usize>,
+    indirect: bool,
+}
+
+fn flow(instruction_set: &InstructionSet, line: &Line) -> Flow {
+    let (definition, parameters) = match line {
+        Line::Instruction {
+            definition,
+            parameters,
+            ..
+        } => (definition, parameters),
+        Line::Data { .. } => {
+            return Flow {
+                next: false,
+                jump: None,
+                indirect: false,
+            }
+        }
+    };
+    let (jump, indirect) = match parameters.get(1) {
+        Some(Parameter::Immediate(target)) if *target >= 0 => (Some(*target as usize), false),
+        _ => (None, true),
+    };
+    let core = instruction_set
+        .get(definition.opcode)
+        .and_then(Opcode::core);
+    match (core, parameters.first()) {
+        (Some(Core::Halt), _) => Flow {
+            next: false,
+            jump: None,
+            indirect: false,
+        },
+        (Some(test @ Core::JumpIfTrue), Some(Parameter::Immediate(condition)))
+        | (Some(test @ Core::JumpIfFalse), Some(Parameter::Immediate(condition))) => {
+            let taken = (*condition != 0) == (test == Core::JumpIfTrue);
+            Flow {
+                next: !taken,
+                jump: if taken { jump } else { None },
+                indirect: taken && indirect,
+            }
+        }
+        (Some(Core::JumpIfTrue), _) | (Some(Core::JumpIfFalse), _) => Flow {
+            next: true,
+            jump,
+            indirect,
+        },
+        _ => Flow {
+            next: true,
+            jump: None,
+            indirect: false,
+        },
+    }
+}
+
+impl Analysis {
+    pub fn new(instruction_set: &InstructionSet, memory: &Memory) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut faults = vec![];
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        let mut pending = vec![0];
+        while let Some(address) = pending.pop() {
+            if address >= memory.len() || instructions.contains_key(&address) {
+                continue;
+            }
+            let line = decode(instruction_set, memory, address);
+            if let Line::Data { .. } = line {
+                faults.push(address);
+                continue;
+            }
+            let flow = flow(instruction_set, &line);
+            if flow.next {
+                pending.push(address + line.size());
+            }
+            if let Some(target) = flow.jump {
+                leaders.insert(target);
+                pending.push(target);
+            }
+            if flow.jump.is_some() || flow.indirect {
+                leaders.insert(address + line.size());
+            }
+            instructions.insert(address, line);
+        }
+        faults.sort_unstable();
+
+        let code: BTreeSet<usize> = instructions
+            .values()
+            .flat_map(|line| line.address()..line.address() + line.size())
+            .collect();
+        let self_modifications = instructions
+            .values()
+            .filter_map(|line| match line {
+                Line::Instruction {
+                    address,
+                    definition,
+                    parameters,
+                } if definition.writes => match parameters.last() {
+                    Some(Parameter::Position(target))
+                        if *target >= 0 && code.contains(&(*target as usize)) =>
+                    {
+                        Some(SelfModification {
+                            address: *address,
+                            target: *target as usize,
+                        })
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+
+        let blocks = leaders
+            .iter()
+            .filter(|leader| instructions.contains_key(leader))
+            .map(|&start| {
+                let mut block = Block {
+                    start,
+                    instructions: vec![],
+                    successors: vec![],
+                    indirect: false,
+                };
+                let mut address = start;
+                while let Some(line) = instructions.get(&address) {
+                    block.instructions.push(line.clone());
+                    let flow = flow(instruction_set, line);
+                    address += line.size();
+                    if let Some(target) = flow.jump {
+                        block.successors.push((target, Edge::Jump));
+                    }
+                    block.indirect = flow.indirect;
+                    if !flow.next {
+                        break;
+                    }
+                    if leaders.contains(&address) {
+                        if instructions.contains_key(&address) {
+                            block.successors.push((address, Edge::Next));
+                        }
+                        break;
+                    }
+                }
+                block
+                    .successors
+                    .retain(|(target, _)| instructions.contains_key(target));
+                (start, block)
+            })
+            .collect();
+
+        Self {
+            blocks,
+            self_modifications,
+            faults,
+            code,
+            len: memory.len(),
+        }
+    }
+
+    /// Whether the word at `address` is part of a reachable instruction.
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.contains(&address)
+    }
+
+    /// The ranges of addresses in the program that hold reachable instructions.
+    pub fn code_regions(&self) -> Vec<Range<usize>> {
+        self.regions(true)
+    }
+
+    /// The ranges of addresses in the program that do not hold reachable instructions.
+    pub fn data_regions(&self) -> Vec<Range<usize>> {
+        self.regions(false)
+    }
+
+    fn regions(&self, code: bool) -> Vec<Range<usize>> {
+        let mut regions: Vec<Range<usize>> = vec![];
+        for address in (0..self.len).filter(|address| self.is_code(*address) == code) {
+            match regions.last_mut() {
+                Some(region) if region.end == address => region.end += 1,
+                _ => regions.push(address..address + 1),
+            }
+        }
+
+        regions
+    }
+
+    /// Renders the control flow graph in the Graphviz DOT language.
+    pub fn to_dot(&self) -> String {
+        let mut dot =
+            String::from("digraph intcode {\n    node [shape=box, fontname=monospace];\n");
+        let modified: BTreeSet<usize> = self
+            .self_modifications
+            .iter()
+            .map(|modification| modification.address)
+            .collect();
+        for block in self.blocks.values() {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|line| {
+                    let marker = if modified.contains(&line.address()) {
+                        " (writes code)"
+                    } else {
+                        ""
+                    };
+                    format!("{:04}: {}{}\\l", line.address(), line, marker)
+                })
+                .collect();
+            dot.push_str(&format!("    b{} [label=\"{}\"];\n", block.start, label));
+            for (target, edge) in &block.successors {
+                let label = match edge {
+                    Edge::Next => "next",
+                    Edge::Jump => "jump",
+                };
+                dot.push_str(&format!(
+                    "    b{} -> b{} [label={}];\n",
+                    block.start, target, label
+                ));
+            }
+            if block.indirect {
+                dot.push_str(&format!(
+                    "    b{} -> unknown [style=dashed];\n",
+                    block.start
+                ));
+            }
+        }
+        if self.blocks.values().any(|block| block.indirect) {
+            dot.push_str("    unknown [label=\"?\", shape=circle];\n");
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Analysis, Edge, SelfModification};
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::{Effect, InstructionSet, Memory, Opcode};
+
+    #[test]
+    fn test_blocks() {
+        let memory = assemble(
+            "
+                IN -> [count]
+        loop:   OUT [count]
+                ADD [count], -1 -> [count]
+                JNZ [count], loop
+                HLT
+        count:  DATA 0
+                DATA 7
+            ",
+        )
+        .expect("Could not assemble");
+        let analysis = Analysis::new(&InstructionSet::core(), &memory);
+        let starts: Vec<usize> = analysis.blocks.keys().copied().collect();
+        assert_eq!(vec![0, 2, 11], starts);
+        assert_eq!(vec![(2, Edge::Next)], analysis.blocks[&0].successors);
+        assert_eq!(
+            vec![(2, Edge::Jump), (11, Edge::Next)],
+            analysis.blocks[&2].successors
+        );
+        assert_eq!(11, analysis.blocks[&2].end());
+        assert_eq!(vec![0..12], analysis.code_regions());
+        assert_eq!(vec![12..14], analysis.data_regions());
+        assert!(analysis.self_modifications.is_empty());
+        assert!(analysis.faults.is_empty());
+    }
+
+    #[test]
+    fn test_unconditional_jumps() {
+        let memory = assemble(
+            "
+                JNZ 1, end
+                DATA 12345
+                JZ 1, end
+        end:    JZ 0, [target]
+        target: DATA 0
+            ",
+        )
+        .expect("Could not assemble");
+        let analysis = Analysis::new(&InstructionSet::core(), &memory);
+        assert_eq!(vec![(7, Edge::Jump)], analysis.blocks[&0].successors);
+        assert!(analysis.blocks[&7].indirect);
+        assert_eq!(vec![0..3, 7..10], analysis.code_regions());
+        assert_eq!(vec![3..7, 10..11], analysis.data_regions());
+        assert!(analysis.faults.is_empty());
+
+        let analysis = Analysis::new(
+            &InstructionSet::core(),
+            &Memory::from(vec![1101, 1, 1, 9, 77]),
+        );
+        assert_eq!(vec![4], analysis.faults);
+    }
+
+    #[test]
+    fn test_self_modification() {
+        // Turns the ADD at address 4 into a MUL before running it.
+        let memory = assemble(
+            "
+                ADD [patched], 1 -> [patched]
+        patched: ADD 3, 4 -> [result]
+                OUT [result]
+                HLT
+        result: DATA 0
+            ",
+        )
+        .expect("Could not assemble");
+        let analysis = Analysis::new(&InstructionSet::core(), &memory);
+        assert_eq!(
+            vec![SelfModification {
+                address: 0,
+                target: 4
+            }],
+            analysis.self_modifications
+        );
+
+        let dot = analysis.to_dot();
+        assert!(dot.starts_with("digraph intcode {\n"));
+        assert!(dot.contains("b0 [label=\"0000: ADD [4], 1 -> [4] (writes code)\\l0004: "));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_registered_opcodes() {
+        let mut instruction_set = InstructionSet::core();
+        instruction_set.register(Opcode::new(50, "DBG", 1, false, |_| Ok(Effect::Next)));
+        let memory = Memory::from(vec![150, 7, 99]);
+        let analysis = Analysis::new(&instruction_set, &memory);
+        assert_eq!(vec![0..3], analysis.code_regions());
+        assert!(analysis.faults.is_empty());
+        assert_eq!(
+            vec![0],
+            Analysis::new(&InstructionSet::core(), &memory).faults
+        );
+    }
+}
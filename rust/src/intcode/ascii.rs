@@ -0,0 +1,138 @@
+use super::{Computer, IntcodeError, State};
+use std::io;
+
+/// Everything a program printed before it stopped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Response {
+    pub text: String,
+    /// Output values that are not ASCII characters, such as a final score.
+    pub values: Vec<i64>,
+    /// Whether the program halted, rather than waiting for another line of input.
+    pub halted: bool,
+}
+
+/// Talks to a `Computer` that reads and writes ASCII text.
+pub struct Ascii {
+    pub computer: Computer,
+}
+
+impl Ascii {
+    pub fn new(computer: Computer) -> Self {
+        Self { computer }
+    }
+
+    /// Queues the bytes of `line`, followed by a newline.
+    pub fn send_line(&mut self, line: &str) {
+        for byte in line.bytes() {
+            self.computer.push_input(i64::from(byte));
+        }
+        self.computer.push_input(i64::from(b'\n'));
+    }
+
+    /// Runs the program until it halts or needs more input than has been sent.
+    pub fn run(&mut self) -> Result<Response, IntcodeError> {
+        let mut response = Response::default();
+        loop {
+            match self.computer.resume()? {
+                State::Output(value) => match value {
+                    0..=127 => response.text.push(value as u8 as char),
+                    _ => response.values.push(value),
+                },
+                State::NeedsInput => return Ok(response),
+                State::Halted => {
+                    response.halted = true;
+                    return Ok(response);
+                }
+                State::Running => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Plays a text-based program interactively, sending each line of `input` to the program and
+/// writing everything it prints to `output`.
+pub fn play<R: io::BufRead, W: io::Write>(
+    ascii: &mut Ascii,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    let mut lines = input.lines();
+    loop {
+        let response = ascii.run().map_err(io::Error::other)?;
+        write!(output, "{}", response.text)?;
+        for value in response.values {
+            writeln!(output, "{}", value)?;
+        }
+        output.flush()?;
+        if response.halted {
+            return Ok(());
+        }
+        match lines.next() {
+            Some(line) => ascii.send_line(&line?),
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{play, Ascii, Response};
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::Computer;
+
+    /// Prints a prompt, echoes one line back in upper case, then outputs the line's length.
+    fn shout() -> Ascii {
+        let memory = assemble(
+            "
+                OUT 62
+                OUT 32
+        read:   IN -> [char]
+                EQ [char], 10 -> [flag]
+                JNZ [flag], done
+                ADD [length], 1 -> [length]
+                LT [char], 97 -> [flag]
+                JNZ [flag], echo
+                ADD [char], -32 -> [char]
+        echo:   OUT [char]
+                JNZ 1, read
+        done:   OUT 10
+                OUT [length]
+                HLT
+        char:   DATA 0
+        flag:   DATA 0
+        length: DATA 1000
+            ",
+        )
+        .expect("Could not assemble");
+        Ascii::new(Computer::new(memory))
+    }
+
+    #[test]
+    fn test_run() {
+        let mut ascii = shout();
+        assert_eq!(
+            Ok(Response {
+                text: "> ".to_string(),
+                values: vec![],
+                halted: false
+            }),
+            ascii.run()
+        );
+        ascii.send_line("hello, world");
+        assert_eq!(
+            Ok(Response {
+                text: "HELLO, WORLD\n".to_string(),
+                values: vec![1012],
+                halted: true
+            }),
+            ascii.run()
+        );
+    }
+
+    #[test]
+    fn test_play() {
+        let mut output = vec![];
+        play(&mut shout(), "abc\nignored\n".as_bytes(), &mut output).expect("Could not play");
+        assert_eq!("> ABC\n1003\n", String::from_utf8_lossy(&output));
+    }
+}
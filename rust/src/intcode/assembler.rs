@@ -0,0 +1,385 @@
+use super::disassembler::{Definition, Line, Parameter};
+use super::{instruction_set, InstructionSet, Memory};
+use std::collections::HashMap;
+use std::{error, fmt};
+
+/// An error in assembly source. `line` is 1-based.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AssemblyError {
+    fn new(line: usize, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AssemblyError {}
+
+/// A number, a label, or a label with a numeric offset.
+#[derive(Debug)]
+enum Expression {
+    Number(i64),
+    Label(String, i64),
+}
+
+impl Expression {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if let Ok(number) = text.parse() {
+            return Ok(Expression::Number(number));
+        }
+        let (label, offset) = match text.find(['+', '-']) {
+            Some(index) => {
+                let offset = text[index..]
+                    .replace(' ', "")
+                    .parse()
+                    .map_err(|_| format!("Invalid offset in {:?}", text))?;
+                (text[..index].trim(), offset)
+            }
+            None => (text, 0),
+        };
+        if !is_identifier(label) {
+            return Err(format!("Invalid value {:?}", text));
+        }
+
+        Ok(Expression::Label(label.to_string(), offset))
+    }
+
+    fn resolve(&self, labels: &HashMap<String, usize>) -> Result<i64, String> {
+        match self {
+            Expression::Number(number) => Ok(*number),
+            Expression::Label(label, offset) => {
+                let address = labels
+                    .get(label)
+                    .ok_or_else(|| format!("Unknown label {:?}", label))?;
+                (*address as i64)
+                    .checked_add(*offset)
+                    .ok_or_else(|| format!("Offset out of range for label {:?}", label))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Operand {
+    Position(Expression),
+    Immediate(Expression),
+    Relative(Expression),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if !text.starts_with('[') {
+            return Ok(Operand::Immediate(Expression::parse(text)?));
+        }
+        let inner = text
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .ok_or_else(|| format!("Unbalanced brackets in {:?}", text))?
+            .trim();
+        match inner.strip_prefix("rb") {
+            Some("") => Ok(Operand::Relative(Expression::Number(0))),
+            Some(offset) if offset.starts_with(['+', '-']) => {
+                let offset = offset.trim_start_matches('+');
+                Ok(Operand::Relative(Expression::parse(offset)?))
+            }
+            _ => Ok(Operand::Position(Expression::parse(inner)?)),
+        }
+    }
+
+    fn resolve(&self, labels: &HashMap<String, usize>) -> Result<Parameter, String> {
+        Ok(match self {
+            Operand::Position(expression) => Parameter::Position(expression.resolve(labels)?),
+            Operand::Immediate(expression) => Parameter::Immediate(expression.resolve(labels)?),
+            Operand::Relative(expression) => Parameter::Relative(expression.resolve(labels)?),
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction(Definition, Vec<Operand>),
+    Data(Vec<Expression>),
+}
+
+impl Statement {
+    fn parse(instruction_set: &InstructionSet, text: &str) -> Result<Self, String> {
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(index) => (&text[..index], text[index..].trim()),
+            None => (text, ""),
+        };
+        if mnemonic.eq_ignore_ascii_case("DATA") {
+            return rest
+                .split(',')
+                .map(Expression::parse)
+                .collect::<Result<_, _>>()
+                .map(Statement::Data);
+        }
+
+        let definition = instruction_set
+            .find(mnemonic)
+            .ok_or_else(|| format!("Unknown mnemonic {:?}", mnemonic))?
+            .definition();
+        let (reads, write) = match rest.find("->") {
+            Some(index) => (rest[..index].trim(), Some(rest[index + 2..].trim())),
+            None => (rest, None),
+        };
+        let mut operands: Vec<Operand> = if reads.is_empty() {
+            vec![]
+        } else {
+            reads
+                .split(',')
+                .map(Operand::parse)
+                .collect::<Result<_, _>>()?
+        };
+        if operands.len() != definition.reads {
+            return Err(format!(
+                "{} takes {} parameters before \"->\", found {}",
+                definition.mnemonic,
+                definition.reads,
+                operands.len()
+            ));
+        }
+        match (definition.writes, write) {
+            (true, Some(target)) => match Operand::parse(target)? {
+                Operand::Immediate(_) => {
+                    return Err(format!("Cannot write to immediate value {:?}", target))
+                }
+                operand => operands.push(operand),
+            },
+            (true, None) => {
+                return Err(format!(
+                    "{} needs a target after \"->\"",
+                    definition.mnemonic
+                ))
+            }
+            (false, Some(_)) => {
+                return Err(format!("{} does not write to memory", definition.mnemonic))
+            }
+            (false, None) => {}
+        }
+
+        Ok(Statement::Instruction(definition, operands))
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(definition, _) => 1 + definition.arity(),
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && text != "rb"
+}
+
+/// Assembles mnemonic source into memory.
+///
+/// Each line holds an optional series of `label:` definitions followed by an optional
+/// statement; `#` and `;` start comments. Statements are either instructions such as
+/// `ADD [x], 3 -> [rb+1]`, where `[x]` is a position, `3` is immediate and `[rb+1]` is relative
+/// to the relative base, or `DATA` directives with comma-separated values. Labels evaluate to
+/// their address, optionally with an offset such as `x+1`. A numeric prefix such as `0004:`, as
+/// produced by `disassembler::listing`, asserts the address of the line.
+pub fn assemble(source: &str) -> Result<Memory, AssemblyError> {
+    assemble_with(&instruction_set::core(), source)
+}
+
+/// Assembles source like `assemble`, with the mnemonics of `instruction_set`.
+pub fn assemble_with(
+    instruction_set: &InstructionSet,
+    source: &str,
+) -> Result<Memory, AssemblyError> {
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    let mut address = 0;
+    for (index, raw_line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut text = raw_line.split(['#', ';']).next().unwrap_or("").trim();
+        while let Some(colon) = text.find(':') {
+            let prefix = text[..colon].trim();
+            if let Ok(expected) = prefix.parse::<usize>() {
+                if expected != address {
+                    return Err(AssemblyError::new(
+                        line_number,
+                        format!("Expected address {}, found {}", expected, address),
+                    ));
+                }
+            } else if is_identifier(prefix) {
+                if labels.insert(prefix.to_string(), address).is_some() {
+                    return Err(AssemblyError::new(
+                        line_number,
+                        format!("Duplicate label {:?}", prefix),
+                    ));
+                }
+            } else {
+                return Err(AssemblyError::new(
+                    line_number,
+                    format!("Invalid label {:?}", prefix),
+                ));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let statement = Statement::parse(instruction_set, text)
+            .map_err(|message| AssemblyError::new(line_number, message))?;
+        address += statement.size();
+        statements.push((line_number, statement));
+    }
+
+    let mut words = Vec::with_capacity(address);
+    for (line_number, statement) in statements {
+        let to_error = |message| AssemblyError::new(line_number, message);
+        match statement {
+            Statement::Instruction(definition, operands) => {
+                let parameters = operands
+                    .iter()
+                    .map(|operand| operand.resolve(&labels))
+                    .collect::<Result<_, _>>()
+                    .map_err(to_error)?;
+                let line = Line::Instruction {
+                    address: words.len(),
+                    definition,
+                    parameters,
+                };
+                words.extend(line.words());
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    words.push(value.resolve(&labels).map_err(to_error)?);
+                }
+            }
+        }
+    }
+
+    Ok(Memory::from(words))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, AssemblyError};
+    use crate::intcode::disassembler::listing;
+    use crate::intcode::{Computer, InstructionSet, Memory};
+
+    #[test]
+    fn test_assemble() {
+        let memory = assemble(
+            "
+            # Day 2 example
+            ADD [9], [10] -> [3]
+            MUL [3], [11] -> [0]
+            HLT
+            DATA 30, 40, 50
+            ",
+        )
+        .expect("Could not assemble");
+        assert_eq!(
+            vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+            memory.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let source = "
+            IN -> [value]
+            LT [value], 8 -> [flag]   ; compare with eight
+            JZ [flag], not_less
+            OUT 999
+            HLT
+        not_less:
+            EQ [value], 8 -> [flag]
+            OUT [flag]
+            HLT
+        value: DATA 0
+        flag:  DATA 0
+        ";
+        let memory = assemble(source).expect("Could not assemble");
+        assert_eq!(
+            vec![
+                3, 19, 1007, 19, 8, 20, 1006, 20, 12, 104, 999, 99, 1008, 19, 8, 20, 4, 20, 99, 0,
+                0
+            ],
+            memory.to_vec()
+        );
+        for (input, expected) in [(3, 999), (8, 1), (9, 0)].iter() {
+            let mut computer = Computer::new(memory.clone());
+            assert_eq!(Ok(Some(*expected)), computer.execute(Some(*input)));
+        }
+    }
+
+    #[test]
+    fn test_relative_and_offsets() {
+        let memory = assemble(
+            "
+            ARB buffer
+            ADD [rb], [rb+1] -> [rb-1]
+            OUT [buffer - 1]
+            HLT
+            DATA 0
+        buffer:
+            DATA 20, 22
+            ",
+        )
+        .expect("Could not assemble");
+        assert_eq!(
+            vec![109, 10, 22201, 0, 1, -1, 4, 9, 99, 0, 20, 22],
+            memory.to_vec()
+        );
+        assert_eq!(Ok(Some(42)), Computer::new(memory).execute(None));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let memory = Memory::from(vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99, 109, -7, 21201, 5, 2, -3, 99,
+        ]);
+        let reassembled =
+            assemble(&listing(&InstructionSet::core(), &memory)).expect("Could not assemble");
+        assert_eq!(memory, reassembled);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_assembly_error("HLT\nNOP", 2, "Unknown mnemonic \"NOP\"");
+        assert_assembly_error("ADD 1, 2 -> 5", 1, "Cannot write to immediate value \"5\"");
+        assert_assembly_error("JNZ 1, nowhere", 1, "Unknown label \"nowhere\"");
+        assert_assembly_error(
+            "ADD 1 -> [0]",
+            1,
+            "ADD takes 2 parameters before \"->\", found 1",
+        );
+        assert_assembly_error("OUT 1\n3: HLT", 2, "Expected address 3, found 2");
+        assert_assembly_error("x: DATA 1\nx: HLT", 2, "Duplicate label \"x\"");
+        assert_assembly_error(
+            "DATA 0\nx: DATA x+9223372036854775807",
+            2,
+            "Offset out of range for label \"x\"",
+        );
+    }
+
+    fn assert_assembly_error(source: &str, line: usize, message: &str) {
+        assert_eq!(
+            Some(AssemblyError::new(line, message.to_string())),
+            assemble(source).err()
+        );
+    }
+}
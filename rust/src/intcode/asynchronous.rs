@@ -0,0 +1,153 @@
+use super::{Computer, IntcodeError, State};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+impl Computer {
+    /// The async form of `execute`: runs the program from the start until it halts, awaiting
+    /// values from `input` whenever it runs out of queued input, and sending output values to
+    /// `output`. Returns the last output. Stops with `IntcodeError::OutputFailed` when the sink
+    /// returns an error, for example because its receiver was dropped.
+    pub async fn execute_async<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+    ) -> Result<Option<i64>, IntcodeError>
+    where
+        I: Stream<Item = i64> + Unpin,
+        O: Sink<i64> + Unpin,
+    {
+        self.restart(None);
+        let mut last_output = None;
+        loop {
+            match self.resume()? {
+                State::NeedsInput => match input.next().await {
+                    Some(value) => self.push_input(value),
+                    None => {
+                        return Err(IntcodeError::MissingInput {
+                            address: self.pointer,
+                        })
+                    }
+                },
+                State::Output(value) => {
+                    last_output = Some(value);
+                    if output.send(value).await.is_err() {
+                        return Err(self.output_failed());
+                    }
+                }
+                State::Halted => {
+                    if output.flush().await.is_err() {
+                        return Err(self.output_failed());
+                    }
+                    return Ok(last_output);
+                }
+                State::Running => unreachable!(),
+            }
+        }
+    }
+
+    fn output_failed(&self) -> IntcodeError {
+        IntcodeError::OutputFailed {
+            address: self.pointer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::amplifier::{Amplifiers, Wiring};
+    use crate::intcode::{Computer, IntcodeError, Memory};
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use futures::future::try_join_all;
+    use futures::{sink, stream};
+
+    #[test]
+    fn test_matches_execute() {
+        // Outputs 999, 1000 or 1001 for inputs below, equal to or above 8.
+        let program = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        for input in 6..=10 {
+            let mut computer = Computer::new(program.clone());
+            let expected = computer.execute(Some(input));
+
+            let mut computer = Computer::new(program.clone());
+            let mut outputs = vec![];
+            let actual = block_on(computer.execute_async(stream::iter(vec![input]), &mut outputs));
+            assert_eq!(expected, actual);
+            assert_eq!(expected.ok().flatten(), outputs.last().copied());
+        }
+
+        let mut computer = Computer::new(vec![3, 0, 3, 0, 99]);
+        assert_eq!(
+            Err(IntcodeError::MissingInput { address: 2 }),
+            block_on(computer.execute_async(stream::iter(vec![1]), sink::drain()))
+        );
+    }
+
+    #[test]
+    fn test_feedback_tasks() {
+        let program = Memory::from(vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ]);
+        let phases = [9, 8, 7, 6, 5];
+        let (senders, mut receivers): (Vec<_>, Vec<_>) =
+            phases.iter().map(|_| mpsc::unbounded()).unzip();
+        for (sender, phase) in senders.iter().zip(phases.iter()) {
+            sender.unbounded_send(*phase).expect("Could not send");
+        }
+        senders[0].unbounded_send(0).expect("Could not send");
+
+        let mut computers: Vec<Computer> = phases
+            .iter()
+            .map(|_| Computer::new(program.clone()))
+            .collect();
+        let tasks = computers
+            .iter_mut()
+            .zip(receivers.iter_mut())
+            .enumerate()
+            .map(|(index, (computer, receiver))| {
+                let sender = senders[(index + 1) % phases.len()].clone();
+                computer.execute_async(receiver, sender)
+            });
+        let results = block_on(try_join_all(tasks)).expect("Program failed");
+
+        assert_eq!(Some(139_629_729), results[phases.len() - 1]);
+        // The first amplifier has halted, so the final signal is still in its channel.
+        assert_eq!(Ok(139_629_729), receivers[0].try_recv());
+        assert_eq!(
+            Ok(results[phases.len() - 1]),
+            Amplifiers::new(&program, &phases, Wiring::Feedback).run(0)
+        );
+    }
+
+    #[test]
+    fn test_output_failed() {
+        let (sender, receiver) = mpsc::unbounded();
+        drop(receiver);
+        let mut computer = Computer::new(vec![104, 1, 104, 2, 99]);
+        assert_eq!(
+            Err(IntcodeError::OutputFailed { address: 2 }),
+            block_on(computer.execute_async(stream::empty(), sender))
+        );
+    }
+
+    #[test]
+    fn test_limits_reset() {
+        // Counts down from three, then outputs its input. Runs 10 instructions.
+        let program = vec![
+            3, 16, 1101, 3, 0, 17, 1001, 17, -1, 17, 1005, 17, 6, 4, 16, 99, 0, 0,
+        ];
+        let mut computer = Computer::new(program);
+        computer.set_instruction_limit(Some(12));
+        for input in 1..=2 {
+            assert_eq!(
+                Ok(Some(input)),
+                block_on(computer.execute_async(stream::iter(vec![input]), sink::drain()))
+            );
+            assert_eq!(10, computer.instructions_executed());
+        }
+    }
+}
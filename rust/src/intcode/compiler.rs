@@ -0,0 +1,382 @@
+use super::disassembler::{decode, Line, Parameter};
+use super::{instruction_set, Computer, Input, IntcodeError, Memory, Output, State};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A parameter with its mode resolved at compile time.
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Immediate(i64),
+    Position(usize),
+    Relative(i64),
+}
+
+impl Operand {
+    /// Returns `None` for parameters that always fail, which are left to the interpreter.
+    fn new(parameter: &Parameter) -> Option<Self> {
+        match *parameter {
+            Parameter::Immediate(value) => Some(Operand::Immediate(value)),
+            Parameter::Position(address) if address >= 0 => {
+                Some(Operand::Position(address as usize))
+            }
+            Parameter::Position(_) => None,
+            Parameter::Relative(offset) => Some(Operand::Relative(offset)),
+        }
+    }
+
+    fn address(self, computer: &Computer) -> Result<usize, IntcodeError> {
+        match self {
+            Operand::Position(address) => Ok(address),
+            Operand::Relative(offset) => {
+                let address = computer
+                    .relative_base
+                    .checked_add(offset)
+                    .ok_or_else(|| computer.overflow())?;
+                if address < 0 {
+                    return Err(IntcodeError::NegativeAddress {
+                        address,
+                        pointer: computer.pointer,
+                    });
+                }
+                Ok(address as usize)
+            }
+            Operand::Immediate(_) => unreachable!("Immediate write parameters do not compile"),
+        }
+    }
+
+    fn read(self, computer: &Computer) -> Result<i64, IntcodeError> {
+        match self {
+            Operand::Immediate(value) => Ok(value),
+            _ => Ok(computer.memory.get(self.address(computer)?)),
+        }
+    }
+}
+
+type Op = Box<dyn Fn(&mut Computer) -> Result<State, IntcodeError> + Send + Sync>;
+
+fn arithmetic<F>(operands: &[Operand], next: usize, operation: F) -> Op
+where
+    F: Fn(i64, i64) -> Option<i64> + Send + Sync + 'static,
+{
+    let (left, right, target) = (operands[0], operands[1], operands[2]);
+    Box::new(move |computer| {
+        let address = target.address(computer)?;
+        let result = operation(left.read(computer)?, right.read(computer)?)
+            .ok_or_else(|| computer.overflow())?;
+        computer.write(address, result);
+        computer.pointer = next;
+        Ok(State::Running)
+    })
+}
+
+fn jump(operands: &[Operand], next: usize, test: bool) -> Op {
+    let (condition, target) = (operands[0], operands[1]);
+    Box::new(move |computer| {
+        if (condition.read(computer)? != 0) == test {
+            let target = target.read(computer)?;
+            if target < 0 {
+                return Err(IntcodeError::PointerOutOfBounds { pointer: target });
+            }
+            computer.pointer = target as usize;
+        } else {
+            computer.pointer = next;
+        }
+        Ok(State::Running)
+    })
+}
+
+/// Translates the instruction on a line into a closure with its parameters bound.
+fn compile_line(line: &Line) -> Option<Op> {
+    let (address, definition, parameters) = match line {
+        Line::Instruction {
+            address,
+            definition,
+            parameters,
+        } => (*address, definition, parameters),
+        Line::Data { .. } => return None,
+    };
+    let operands = parameters
+        .iter()
+        .map(Operand::new)
+        .collect::<Option<Vec<Operand>>>()?;
+    let next = address + line.size();
+    let op: Op = match definition.opcode {
+        1 => arithmetic(&operands, next, i64::checked_add),
+        2 => arithmetic(&operands, next, i64::checked_mul),
+        3 => {
+            let target = operands[0];
+            Box::new(move |computer| {
+                let address = target.address(computer)?;
+                match computer.next_input() {
+                    Some(value) => {
+                        computer.write(address, value);
+                        computer.pointer = next;
+                        Ok(State::Running)
+                    }
+                    None => Ok(State::NeedsInput),
+                }
+            })
+        }
+        4 => {
+            let value = operands[0];
+            Box::new(move |computer| {
+                let value = value.read(computer)?;
+                computer.pointer = next;
+                Ok(State::Output(value))
+            })
+        }
+        5 => jump(&operands, next, true),
+        6 => jump(&operands, next, false),
+        7 => arithmetic(&operands, next, |left, right| Some((left < right) as i64)),
+        8 => arithmetic(&operands, next, |left, right| Some((left == right) as i64)),
+        9 => {
+            let offset = operands[0];
+            Box::new(move |computer| {
+                computer.relative_base = computer
+                    .relative_base
+                    .checked_add(offset.read(computer)?)
+                    .ok_or_else(|| computer.overflow())?;
+                computer.pointer = next;
+                Ok(State::Running)
+            })
+        }
+        99 => Box::new(|_| Ok(State::Halted)),
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+/// A program translated ahead of time into one closure per instruction, with the parameter
+/// modes and values of each instruction bound into it.
+#[derive(Clone)]
+pub struct Program {
+    memory: Memory,
+    /// The compiled instruction and its size for every address that decodes to one.
+    ops: Arc<Vec<Option<(Op, usize)>>>,
+}
+
+/// Compiles every address of `memory` that holds a valid instruction, since jumps can land on
+/// any of them.
+pub fn compile(memory: &Memory) -> Program {
+    let instruction_set = instruction_set::core();
+    let ops = (0..memory.len())
+        .map(|address| {
+            let line = decode(&instruction_set, memory, address);
+            compile_line(&line).map(|op| (op, line.size()))
+        })
+        .collect();
+
+    Program {
+        memory: memory.clone(),
+        ops: Arc::new(ops),
+    }
+}
+
+impl Program {
+    pub fn computer(&self) -> CompiledComputer {
+        CompiledComputer {
+            computer: Computer::new(self.memory.clone()),
+            compiled: Compiled {
+                ops: Arc::clone(&self.ops),
+                valid: self.ops.iter().map(Option::is_some).collect(),
+            },
+        }
+    }
+}
+
+/// Runs a compiled `Program` with the same results as `Computer`. Once the program writes to
+/// a word of a compiled instruction, that instruction is interpreted from then on. Every
+/// instruction is interpreted while a tracer is set or loop detection is on, so that no events
+/// or jumps are missed.
+pub struct CompiledComputer {
+    computer: Computer,
+    compiled: Compiled,
+}
+
+/// The compiled instructions of a `CompiledComputer`, kept apart from its `Computer` so that
+/// both can be borrowed at once by the run loops of `Computer`.
+struct Compiled {
+    ops: Arc<Vec<Option<(Op, usize)>>>,
+    /// Whether the compiled instruction at each address still matches memory.
+    valid: Vec<bool>,
+}
+
+impl CompiledComputer {
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.computer.memory
+    }
+
+    /// Patches memory, falling back to the interpreter for any instruction it changes.
+    pub fn poke(&mut self, address: usize, value: i64) {
+        self.computer.memory.set(address, value);
+        self.compiled.invalidate(address);
+    }
+
+    pub fn set_input<I: Input + Send + 'static>(&mut self, input: I) {
+        self.computer.set_input(input);
+    }
+
+    pub fn set_output<O: Output + Send + 'static>(&mut self, output: O) {
+        self.computer.set_output(output);
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.computer.push_input(value);
+    }
+
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.computer.set_instruction_limit(limit);
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.computer.set_deadline(deadline);
+    }
+
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.computer.set_loop_detection(enabled);
+    }
+
+    pub fn instructions_executed(&self) -> u64 {
+        self.computer.instructions_executed()
+    }
+
+    pub fn take_outputs(&mut self) -> Vec<i64> {
+        self.computer.take_outputs()
+    }
+
+    /// Runs the program from the start until it halts, returning the last output. Like
+    /// `Computer::execute`, memory is not reset.
+    pub fn execute(&mut self, input: Option<i64>) -> Result<Option<i64>, IntcodeError> {
+        let compiled = &mut self.compiled;
+        self.computer
+            .execute_with(input, |computer| compiled.step(computer))
+    }
+
+    /// Continues until the program halts or runs out of input, like `Computer::run`.
+    pub fn run(&mut self) -> Result<State, IntcodeError> {
+        let compiled = &mut self.compiled;
+        self.computer.run_with(|computer| compiled.step(computer))
+    }
+
+    /// Continues until the program needs input, produces output, or halts.
+    pub fn resume(&mut self) -> Result<State, IntcodeError> {
+        let compiled = &mut self.compiled;
+        self.computer
+            .resume_with(|computer| compiled.step(computer))
+    }
+
+    pub fn step(&mut self) -> Result<State, IntcodeError> {
+        self.compiled.step(&mut self.computer)
+    }
+}
+
+impl Compiled {
+    fn step(&mut self, computer: &mut Computer) -> Result<State, IntcodeError> {
+        let pointer = computer.pointer;
+        let compiled = match self.ops.get(pointer) {
+            Some(Some((op, _)))
+                if self.valid[pointer]
+                    && computer.tracer.is_none()
+                    && computer.loop_detector.is_none() =>
+            {
+                Some(op)
+            }
+            _ => None,
+        };
+        let state = match compiled {
+            Some(op) => {
+                computer.last_write = None;
+                computer.check_limits()?;
+                let state = op(computer)?;
+                if state != State::NeedsInput {
+                    computer.instructions += 1;
+                }
+                state
+            }
+            None => computer.step()?,
+        };
+        if let Some((address, _)) = computer.last_write {
+            self.invalidate(address);
+        }
+
+        Ok(state)
+    }
+
+    /// Marks every compiled instruction that covers `address` as stale.
+    fn invalidate(&mut self, address: usize) {
+        for start in address.saturating_sub(3)..=address {
+            if let Some(Some((_, size))) = self.ops.get(start) {
+                if start + size > address {
+                    self.valid[start] = false;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile;
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::{Computer, State};
+
+    #[test]
+    fn test_self_modifying_code_falls_back() {
+        let memory = assemble(
+            "
+            patch:  OUT 1
+                    JNZ [done], finish
+                    ADD 0, 1 -> [done]
+                    ADD 99, 0 -> [patch]
+                    JNZ 1, patch
+            finish: OUT 2
+                    HLT
+            done:   DATA 0
+            ",
+        )
+        .expect("Could not assemble");
+        let mut computer = compile(&memory).computer();
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![1], computer.take_outputs());
+
+        let mut computer = compile(&vec![104, 1, 1105, 1, 0].into()).computer();
+        assert_eq!(Ok(State::Output(1)), computer.resume());
+        computer.poke(0, 99);
+        assert_eq!(Ok(State::Halted), computer.resume());
+    }
+
+    #[test]
+    fn test_matches_interpreter() {
+        // Sums its inputs until it reads a zero, keeping the total at a relative address.
+        let memory = assemble(
+            "
+                    ARB 100
+            loop:   IN -> [rb+1]
+                    JZ [rb+1], done
+                    ADD [rb], [rb+1] -> [rb]
+                    JNZ 1, loop
+            done:   OUT [rb]
+                    HLT
+            ",
+        )
+        .expect("Could not assemble");
+        let program = compile(&memory);
+        for inputs in [vec![0], vec![1, 2, 3, 0], vec![i64::MAX, 1, 0], vec![5]].iter() {
+            let mut interpreted = Computer::new(memory.clone());
+            let mut compiled = program.computer();
+            for input in inputs {
+                interpreted.push_input(*input);
+                compiled.push_input(*input);
+            }
+            assert_eq!(interpreted.run(), compiled.run());
+            assert_eq!(interpreted.take_outputs(), compiled.take_outputs());
+            assert_eq!(interpreted.memory, *compiled.memory());
+            assert_eq!(interpreted.pointer(), compiled.computer().pointer());
+        }
+    }
+}
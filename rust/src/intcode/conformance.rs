@@ -0,0 +1,361 @@
+use super::compiler::compile;
+use super::{loader, Computer, IntcodeError, State};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::{error, fmt};
+
+/// A program run with its expected results. Expectations that are `None` are not checked.
+///
+/// Cases are written in fixture files as a `[name]` header followed by `key: values` lines,
+/// where the values are comma-separated:
+///
+/// ```text
+/// # Comments start with a hash.
+/// [add and multiply]
+/// program: 1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50
+/// input:
+/// output:
+/// memory: 3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50
+/// ```
+///
+/// An `error` line holds the expected error in its `Debug` form, such as
+/// `error: MissingInput { address: 2 }`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub input: Vec<i64>,
+    pub output: Option<Vec<i64>>,
+    pub memory: Option<Vec<i64>>,
+    pub error: Option<String>,
+}
+
+/// An error in a fixture file. `line` is 1-based.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FixtureError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for FixtureError {}
+
+pub fn parse_cases(text: &str) -> Result<Vec<Case>, FixtureError> {
+    let mut cases: Vec<Case> = vec![];
+    let mut header_lines = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| FixtureError {
+            line: index + 1,
+            message,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            cases.push(Case {
+                name: name.trim().to_string(),
+                ..Case::default()
+            });
+            header_lines.push(index + 1);
+            continue;
+        }
+
+        let case = cases
+            .last_mut()
+            .ok_or_else(|| error("Expected a [name] header before the first case".to_string()))?;
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| error(format!("Expected \"key: values\", found {:?}", line)))?;
+        let value = value.trim();
+        if key.trim() == "error" {
+            case.error = Some(value.to_string());
+            continue;
+        }
+        let values = loader::parse(value.as_bytes())
+            .map_err(|parse_error| error(parse_error.to_string()))?
+            .to_vec();
+        match key.trim() {
+            "program" => case.program = values,
+            "input" => case.input = values,
+            "output" => case.output = Some(values),
+            "memory" => case.memory = Some(values),
+            key => return Err(error(format!("Unknown key {:?}", key))),
+        }
+    }
+
+    match cases.iter().position(|case| case.program.is_empty()) {
+        Some(index) => Err(FixtureError {
+            line: header_lines[index],
+            message: format!("Case {:?} has no program", cases[index].name),
+        }),
+        None => Ok(cases),
+    }
+}
+
+/// What a run produced, in the same shape as the expectations of a `Case`.
+struct Outcome {
+    output: Vec<i64>,
+    memory: Vec<i64>,
+    error: Option<IntcodeError>,
+}
+
+/// Runs until the program halts, like `Computer::execute` with all of the inputs queued.
+fn outcome<F>(mut run: F) -> Outcome
+where
+    F: FnMut() -> (Result<State, IntcodeError>, Vec<i64>, Vec<i64>, usize),
+{
+    let (result, output, memory, pointer) = run();
+    let error = match result {
+        Ok(State::NeedsInput) => Some(IntcodeError::MissingInput { address: pointer }),
+        Ok(_) => None,
+        Err(error) => Some(error),
+    };
+
+    Outcome {
+        output,
+        memory,
+        error,
+    }
+}
+
+fn differences(case: &Case, outcome: &Outcome) -> Vec<String> {
+    let mut differences = vec![];
+    let error = outcome.error.as_ref().map(|error| format!("{:?}", error));
+    if error != case.error {
+        differences.push(format!(
+            "error: expected {}, got {}",
+            case.error.as_deref().unwrap_or("none"),
+            error.as_deref().unwrap_or("none")
+        ));
+    }
+    if let Some(expected) = &case.output {
+        if *expected != outcome.output {
+            differences.push(format!(
+                "output: expected {:?}, got {:?}",
+                expected, outcome.output
+            ));
+        }
+    }
+    if let Some(expected) = &case.memory {
+        if expected.len() != outcome.memory.len() {
+            differences.push(format!(
+                "memory: expected {} words, got {}",
+                expected.len(),
+                outcome.memory.len()
+            ));
+        }
+        for (address, (expected, actual)) in expected.iter().zip(&outcome.memory).enumerate() {
+            if expected != actual {
+                differences.push(format!(
+                    "memory[{}]: expected {}, got {}",
+                    address, expected, actual
+                ));
+            }
+        }
+    }
+
+    differences
+}
+
+/// Runs a case with the interpreter and the compiler, returning a description of every way
+/// the results differ from the expectations.
+pub fn run_case(case: &Case) -> Vec<String> {
+    let interpreted = outcome(|| {
+        let mut computer = Computer::new(case.program.clone());
+        case.input
+            .iter()
+            .for_each(|value| computer.push_input(*value));
+        let result = computer.run();
+        (
+            result,
+            computer.take_outputs(),
+            computer.memory.to_vec(),
+            computer.pointer(),
+        )
+    });
+    let compiled = outcome(|| {
+        let mut computer = compile(&case.program.clone().into()).computer();
+        case.input
+            .iter()
+            .for_each(|value| computer.push_input(*value));
+        let result = computer.run();
+        (
+            result,
+            computer.take_outputs(),
+            computer.memory().to_vec(),
+            computer.computer().pointer(),
+        )
+    });
+
+    let mut differences = differences(case, &interpreted);
+    differences.extend(
+        self::differences(case, &compiled)
+            .into_iter()
+            .map(|difference| format!("compiled {}", difference)),
+    );
+    differences
+}
+
+/// The results of running a set of cases.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub passed: usize,
+    /// The name of each failed case, with its differences.
+    pub failures: Vec<(String, Vec<String>)>,
+}
+
+impl Report {
+    pub fn run(cases: &[Case]) -> Self {
+        let mut report = Self::default();
+        for case in cases {
+            let differences = run_case(case);
+            if differences.is_empty() {
+                report.passed += 1;
+            } else {
+                report.failures.push((case.name.clone(), differences));
+            }
+        }
+
+        report
+    }
+
+    /// Runs the cases of every `.case` file in `directory`. Case names are prefixed with the
+    /// name of their file.
+    pub fn run_directory<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let mut paths = fs::read_dir(directory)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension() == Some(OsStr::new("case")));
+        paths.sort();
+
+        let mut cases = vec![];
+        for path in paths {
+            let file_name = path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+            let text = fs::read_to_string(&path)?;
+            let file_cases = parse_cases(&text).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", file_name, error),
+                )
+            })?;
+            cases.extend(file_cases.into_iter().map(|case| Case {
+                name: format!("{}: {}", file_name, case.name),
+                ..case
+            }));
+        }
+
+        Ok(Self::run(&cases))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, differences) in &self.failures {
+            writeln!(f, "FAILED {}", name)?;
+            for difference in differences {
+                writeln!(f, "    {}", difference)?;
+            }
+        }
+        writeln!(f, "{} passed, {} failed", self.passed, self.failures.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cases, run_case, Case, FixtureError, Report};
+    use std::path::Path;
+
+    #[test]
+    fn test_fixtures() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/intcode");
+        let report = Report::run_directory(directory).expect("Could not run fixtures");
+        assert!(report.failures.is_empty(), "\n{}", report);
+        assert!(report.passed > 0);
+    }
+
+    #[test]
+    fn test_parse_cases() {
+        let cases = parse_cases(
+            "
+            # Reads a value and writes it back out.
+            [echo]
+            program: 3, 0, 4, 0, 99
+            input: 7
+            output: 7
+            memory: 7, 0, 4, 0, 99
+
+            [no input]
+            program: 3, 0, 99
+            error: MissingInput { address: 0 }
+            ",
+        )
+        .expect("Could not parse");
+        assert_eq!(
+            vec![
+                Case {
+                    name: "echo".to_string(),
+                    program: vec![3, 0, 4, 0, 99],
+                    input: vec![7],
+                    output: Some(vec![7]),
+                    memory: Some(vec![7, 0, 4, 0, 99]),
+                    error: None,
+                },
+                Case {
+                    name: "no input".to_string(),
+                    program: vec![3, 0, 99],
+                    error: Some("MissingInput { address: 0 }".to_string()),
+                    ..Case::default()
+                }
+            ],
+            cases
+        );
+        assert!(cases.iter().all(|case| run_case(case).is_empty()));
+
+        assert_eq!(
+            Err(FixtureError {
+                line: 2,
+                message: "Unknown key \"outputs\"".to_string()
+            }),
+            parse_cases("[typo]\noutputs: 1\nprogram: 99")
+        );
+    }
+
+    #[test]
+    fn test_differences() {
+        let case = Case {
+            name: "wrong".to_string(),
+            program: vec![1101, 2, 2, 0, 4, 0, 99],
+            output: Some(vec![5]),
+            memory: Some(vec![5, 2, 2, 0, 4, 0, 99]),
+            ..Case::default()
+        };
+        assert_eq!(
+            vec![
+                "output: expected [5], got [4]",
+                "memory[0]: expected 5, got 4",
+                "compiled output: expected [5], got [4]",
+                "compiled memory[0]: expected 5, got 4",
+            ],
+            run_case(&case)
+        );
+        assert_eq!(
+            "FAILED wrong\n    output: expected [5], got [4]\n    memory[0]: expected 5, got \
+             4\n    compiled output: expected [5], got [4]\n    compiled memory[0]: expected 5, \
+             got 4\n0 passed, 1 failed\n",
+            Report::run(&[case]).to_string()
+        );
+    }
+}
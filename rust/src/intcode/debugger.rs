@@ -0,0 +1,486 @@
+use super::disassembler::decode;
+use super::{Computer, IntcodeError, State};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io;
+
+/// Where execution should pause.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Breakpoint {
+    /// Pause before executing the instruction at an address.
+    Address(usize),
+    /// Pause before executing any instruction with an opcode.
+    Opcode(i64),
+}
+
+/// Why the debugger handed control back to the caller.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// A single step finished without anything of note happening.
+    Stepped,
+    /// The next instruction to execute matches a breakpoint.
+    Breakpoint(Breakpoint),
+    /// The last instruction wrote `value` to a watched address.
+    Watchpoint {
+        address: usize,
+        value: i64,
+    },
+    /// The last instruction produced an output value.
+    Output(i64),
+    NeedsInput,
+    Halted,
+}
+
+/// Runs a `Computer` under the control of breakpoints and watchpoints.
+pub struct Debugger {
+    pub computer: Computer,
+    breakpoints: BTreeSet<Breakpoint>,
+    watchpoints: BTreeSet<usize>,
+}
+
+impl Debugger {
+    pub fn new(computer: Computer) -> Self {
+        Self {
+            computer,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.insert(breakpoint);
+    }
+
+    /// Returns whether the breakpoint existed.
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.breakpoints.remove(&breakpoint)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.iter()
+    }
+
+    pub fn watch(&mut self, address: usize) {
+        self.watchpoints.insert(address);
+    }
+
+    /// Returns whether the address was being watched.
+    pub fn unwatch(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &usize> {
+        self.watchpoints.iter()
+    }
+
+    /// The instruction pointer and relative base.
+    pub fn registers(&self) -> (usize, i64) {
+        (self.computer.pointer(), self.computer.relative_base())
+    }
+
+    /// Reads `count` memory values starting at `address`, but never more than `PEEK_LIMIT`.
+    pub fn peek(&self, address: usize, count: usize) -> Vec<i64> {
+        (address..address.saturating_add(count.min(PEEK_LIMIT)))
+            .map(|address| self.computer.memory.get(address))
+            .collect()
+    }
+
+    pub fn poke(&mut self, address: usize, value: i64) {
+        self.computer.memory.set(address, value);
+    }
+
+    /// Executes a single instruction, ignoring breakpoints. Output values are also passed on to
+    /// the computer's output sink or queue.
+    pub fn step(&mut self) -> Result<Event, IntcodeError> {
+        let state = self.computer.step()?;
+        if let Some((address, value)) = self.computer.last_write() {
+            if self.watchpoints.contains(&address) {
+                return Ok(Event::Watchpoint { address, value });
+            }
+        }
+
+        Ok(match state {
+            State::Running => Event::Stepped,
+            State::NeedsInput => Event::NeedsInput,
+            State::Output(value) => {
+                self.computer.emit(value);
+                Event::Output(value)
+            }
+            State::Halted => Event::Halted,
+        })
+    }
+
+    /// Runs until a breakpoint or watchpoint triggers, or the program needs input or halts. A
+    /// breakpoint on the current instruction does not trigger, so that execution can continue
+    /// past it.
+    pub fn resume(&mut self) -> Result<Event, IntcodeError> {
+        let mut first = true;
+        loop {
+            if !first {
+                if let Some(breakpoint) = self.breakpoint_hit() {
+                    return Ok(Event::Breakpoint(breakpoint));
+                }
+            }
+            first = false;
+            match self.step()? {
+                Event::Stepped | Event::Output(_) => continue,
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn breakpoint_hit(&self) -> Option<Breakpoint> {
+        let pointer = self.computer.pointer();
+        let address = Breakpoint::Address(pointer);
+        let opcode = Breakpoint::Opcode(self.computer.memory.get(pointer) % 100);
+        [address, opcode]
+            .iter()
+            .find(|breakpoint| self.breakpoints.contains(breakpoint))
+            .copied()
+    }
+
+    /// Describes the result of running the program, including any output it produced.
+    fn report(&mut self, result: Result<Event, IntcodeError>) -> String {
+        let mut lines: Vec<String> = self
+            .computer
+            .take_outputs()
+            .iter()
+            .map(|value| format!("Output: {}", value))
+            .collect();
+        lines.push(match result {
+            Ok(event) => self.describe(event),
+            Err(error) => format!("Error: {}", error),
+        });
+
+        lines.join("\n")
+    }
+
+    fn describe(&self, event: Event) -> String {
+        match event {
+            Event::Stepped | Event::Output(_) => self.current_instruction(),
+            Event::Breakpoint(_) => format!("Breakpoint\n{}", self.current_instruction()),
+            Event::Watchpoint { address, value } => format!(
+                "Watchpoint: [{}] = {}\n{}",
+                address,
+                value,
+                self.current_instruction()
+            ),
+            Event::NeedsInput => format!(
+                "Waiting for input (use \"input VALUE\")\n{}",
+                self.current_instruction()
+            ),
+            Event::Halted => "Halted".to_string(),
+        }
+    }
+
+    fn current_instruction(&self) -> String {
+        self.disassemble(self.computer.pointer(), 1)
+    }
+
+    fn disassemble(&self, address: usize, count: usize) -> String {
+        let mut lines = vec![];
+        let mut address = address;
+        for _ in 0..count {
+            let line = decode(
+                self.computer.instruction_set(),
+                &self.computer.memory,
+                address,
+            );
+            let marker = if address == self.computer.pointer() {
+                "=>"
+            } else {
+                "  "
+            };
+            lines.push(format!("{} {:04}: {}", marker, address, line));
+            address = match address.checked_add(line.size()) {
+                Some(next) if next < self.computer.memory.len() => next,
+                _ => break,
+            };
+        }
+
+        lines.join("\n")
+    }
+
+    /// Runs a single REPL command and returns the text to show. Returns `None` when the command
+    /// asks to quit.
+    pub fn command(&mut self, line: &str) -> Option<String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Some(String::new()),
+        };
+        let arguments: Vec<&str> = words.collect();
+        let (opcode, arguments) = match arguments.split_first() {
+            Some((&"op", rest)) => (true, rest),
+            _ => (false, arguments.as_slice()),
+        };
+        let numbers: Vec<i64> = match arguments.iter().map(|word| word.parse()).collect() {
+            Ok(numbers) => numbers,
+            Err(_) => return Some(format!("Invalid number in {:?}", line.trim())),
+        };
+        // Every argument is an address or a count, except for opcodes, input values and the
+        // value of `set`.
+        let unsigned: Vec<Option<usize>> = numbers
+            .iter()
+            .map(|value| usize::try_from(*value).ok())
+            .collect();
+        let signed = |index: usize| opcode || name == "input" || (name == "set" && index == 1);
+        if (0..numbers.len()).any(|index| !signed(index) && unsigned[index].is_none()) {
+            return Some(format!("Invalid address or count in {:?}", line.trim()));
+        }
+        let address = |index: usize| unsigned.get(index).copied().flatten();
+
+        let output = match (name, opcode, numbers.as_slice()) {
+            ("q", false, []) | ("quit", false, []) => return None,
+            ("s", false, counts) | ("step", false, counts) if counts.len() <= 1 => {
+                self.step_command(address(0).unwrap_or(1))
+            }
+            ("c", false, []) | ("continue", false, []) => {
+                let result = self.resume();
+                self.report(result)
+            }
+            ("b", _, [value]) | ("break", _, [value]) => {
+                let breakpoint = Self::parse_breakpoint(opcode, *value, address(0));
+                self.add_breakpoint(breakpoint);
+                format!("Added {:?}", breakpoint)
+            }
+            ("d", _, [value]) | ("delete", _, [value]) => {
+                let breakpoint = Self::parse_breakpoint(opcode, *value, address(0));
+                if self.remove_breakpoint(breakpoint) {
+                    format!("Removed {:?}", breakpoint)
+                } else {
+                    format!("No {:?}", breakpoint)
+                }
+            }
+            ("watch", false, [_]) => {
+                self.watch(address(0).unwrap_or(0));
+                format!("Watching [{}]", address(0).unwrap_or(0))
+            }
+            ("unwatch", false, [_]) => {
+                if self.unwatch(address(0).unwrap_or(0)) {
+                    format!("Stopped watching [{}]", address(0).unwrap_or(0))
+                } else {
+                    format!("Not watching [{}]", address(0).unwrap_or(0))
+                }
+            }
+            ("list", false, []) => self
+                .breakpoints()
+                .map(|breakpoint| format!("{:?}", breakpoint))
+                .chain(
+                    self.watchpoints()
+                        .map(|address| format!("Watchpoint([{}])", address)),
+                )
+                .collect::<Vec<String>>()
+                .join("\n"),
+            ("r", false, []) | ("regs", false, []) => {
+                let (pointer, relative_base) = self.registers();
+                format!("pointer: {}\nrelative base: {}", pointer, relative_base)
+            }
+            ("x", false, [_]) | ("x", false, [_, _]) => {
+                let start = address(0).unwrap_or(0);
+                self.peek(start, address(1).unwrap_or(1))
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| format!("[{}] = {}", start + index, value))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+            ("set", false, [_, value]) => {
+                self.poke(address(0).unwrap_or(0), *value);
+                format!("[{}] = {}", address(0).unwrap_or(0), value)
+            }
+            ("input", false, values) if !values.is_empty() => {
+                for value in values {
+                    self.computer.push_input(*value);
+                }
+                format!("Queued {} input value(s)", values.len())
+            }
+            ("dis", false, arguments) if arguments.len() <= 2 => self.disassemble(
+                address(0).unwrap_or_else(|| self.computer.pointer()),
+                address(1).unwrap_or(10),
+            ),
+            _ => HELP.to_string(),
+        };
+
+        Some(output)
+    }
+
+    fn parse_breakpoint(opcode: bool, value: i64, address: Option<usize>) -> Breakpoint {
+        if opcode {
+            Breakpoint::Opcode(value)
+        } else {
+            Breakpoint::Address(address.unwrap_or(0))
+        }
+    }
+
+    fn step_command(&mut self, count: usize) -> String {
+        let mut result = Ok(Event::Stepped);
+        for _ in 0..count.max(1) {
+            result = self.step();
+            if result != Ok(Event::Stepped) {
+                break;
+            }
+        }
+
+        self.report(result)
+    }
+}
+
+/// The most values that `peek` returns, so that a typo in a count cannot exhaust memory.
+pub const PEEK_LIMIT: usize = 1 << 16;
+
+const HELP: &str = "Commands:
+  s, step [COUNT]          execute COUNT instructions (default 1)
+  c, continue              run until a breakpoint, watchpoint, input request or halt
+  b, break ADDRESS         pause before the instruction at ADDRESS
+  b, break op OPCODE       pause before any instruction with OPCODE
+  d, delete [op] VALUE     remove a breakpoint
+  watch ADDRESS            pause after a write to ADDRESS
+  unwatch ADDRESS          remove a watchpoint
+  list                     show breakpoints and watchpoints
+  r, regs                  show the instruction pointer and relative base
+  x ADDRESS [COUNT]        show COUNT memory values starting at ADDRESS
+  set ADDRESS VALUE        patch memory
+  input VALUE...           queue input values
+  dis [ADDRESS] [COUNT]    disassemble COUNT instructions (default: from the pointer)
+  q, quit                  exit";
+
+/// Reads commands from `input` and writes their results to `output` until `quit` or the end of
+/// the input.
+pub fn repl<R: io::BufRead, W: io::Write>(
+    debugger: &mut Debugger,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    write!(output, "(intcode) ")?;
+    output.flush()?;
+    for line in input.lines() {
+        match debugger.command(&line?) {
+            Some(text) => {
+                if !text.is_empty() {
+                    writeln!(output, "{}", text)?;
+                }
+            }
+            None => break,
+        }
+        write!(output, "(intcode) ")?;
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repl, Breakpoint, Debugger, Event, PEEK_LIMIT};
+    use crate::intcode::Computer;
+
+    fn debugger() -> Debugger {
+        // Sums the inputs until it reads a zero, then outputs the total.
+        Debugger::new(Computer::new(vec![
+            3, 15, 1006, 15, 12, 1, 15, 16, 16, 1105, 1, 0, 4, 16, 99, 0, 0,
+        ]))
+    }
+
+    #[test]
+    fn test_step() {
+        let mut debugger = debugger();
+        assert_eq!(Ok(Event::NeedsInput), debugger.step());
+        debugger.computer.push_input(5);
+        assert_eq!(Ok(Event::Stepped), debugger.step());
+        assert_eq!((2, 0), debugger.registers());
+        assert_eq!(vec![5, 0], debugger.peek(15, 2));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger();
+        for value in [3, 4, 0].iter() {
+            debugger.computer.push_input(*value);
+        }
+        debugger.add_breakpoint(Breakpoint::Address(5));
+        assert_eq!(
+            Ok(Event::Breakpoint(Breakpoint::Address(5))),
+            debugger.resume()
+        );
+        assert_eq!(
+            Ok(Event::Breakpoint(Breakpoint::Address(5))),
+            debugger.resume()
+        );
+        assert_eq!(vec![4, 3], debugger.peek(15, 2));
+        assert!(debugger.remove_breakpoint(Breakpoint::Address(5)));
+
+        debugger.add_breakpoint(Breakpoint::Opcode(4));
+        assert_eq!(
+            Ok(Event::Breakpoint(Breakpoint::Opcode(4))),
+            debugger.resume()
+        );
+        assert_eq!(12, debugger.registers().0);
+        assert_eq!(Ok(Event::Output(7)), debugger.step());
+        assert_eq!(Ok(Event::Halted), debugger.resume());
+        assert_eq!(vec![7], debugger.computer.take_outputs());
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = debugger();
+        debugger.computer.push_input(2);
+        debugger.computer.push_input(0);
+        debugger.watch(16);
+        assert_eq!(
+            Ok(Event::Watchpoint {
+                address: 16,
+                value: 2
+            }),
+            debugger.resume()
+        );
+        assert_eq!(9, debugger.registers().0);
+        debugger.poke(16, 40);
+        assert_eq!(Ok(Event::Halted), debugger.resume());
+        assert_eq!(vec![40], debugger.computer.take_outputs());
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut debugger = debugger();
+        let commands = "break 12\ninput 6 0\nc\nx 15 2\nset 16 9\nregs\ns\nquit\nstep\n";
+        let mut output = vec![];
+        repl(&mut debugger, commands.as_bytes(), &mut output).expect("REPL failed");
+        assert_eq!(
+            "(intcode) Added Address(12)
+(intcode) Queued 2 input value(s)
+(intcode) Breakpoint
+=> 0012: OUT [16]
+(intcode) [15] = 0
+[16] = 6
+(intcode) [16] = 9
+(intcode) pointer: 12
+relative base: 0
+(intcode) Output: 9
+=> 0014: HLT
+(intcode) ",
+            String::from_utf8(output).expect("Invalid UTF-8")
+        );
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let mut debugger = debugger();
+        for command in ["x 5 -1", "dis 0 -1", "b -3", "set -1 4"].iter() {
+            assert_eq!(
+                Some(format!("Invalid address or count in {:?}", command)),
+                debugger.command(command)
+            );
+        }
+        assert_eq!(Some("[16] = -4".to_string()), debugger.command("set 16 -4"));
+        assert_eq!(vec![0], debugger.peek(usize::MAX - 1, 2));
+        assert_eq!(PEEK_LIMIT, debugger.peek(0, usize::MAX).len());
+        let dump = debugger.command("x 0 100000000000").expect("Command quit");
+        assert_eq!(PEEK_LIMIT, dump.lines().count());
+        assert_eq!(Some("[65535] = 0"), dump.lines().last());
+        assert_eq!(
+            Some("   0016: DATA -4".to_string()),
+            debugger.command("dis 16 1000000000000")
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// A source of values for input instructions.
+pub trait Input {
+    /// Returns the next value, or `None` if no value is available.
+    fn read(&mut self) -> Option<i64>;
+}
+
+/// A sink for the values produced by output instructions.
+pub trait Output {
+    fn write(&mut self, value: i64);
+}
+
+impl Input for VecDeque<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+/// Blocks until a value arrives, and reports no input once every sender has hung up.
+impl Input for Receiver<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+/// Reads input values from an iterator.
+pub struct IterInput<I>(pub I);
+
+impl<I: Iterator<Item = i64>> Input for IterInput<I> {
+    fn read(&mut self) -> Option<i64> {
+        self.0.next()
+    }
+}
+
+/// Reads input values by calling a closure.
+pub struct FnInput<F>(pub F);
+
+impl<F: FnMut() -> Option<i64>> Input for FnInput<F> {
+    fn read(&mut self) -> Option<i64> {
+        (self.0)()
+    }
+}
+
+impl Output for VecDeque<i64> {
+    fn write(&mut self, value: i64) {
+        self.push_back(value);
+    }
+}
+
+impl Output for Vec<i64> {
+    fn write(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+/// Shares a sink, so that its values can be read back while a computer holds the other handle.
+impl<O: Output> Output for Arc<Mutex<O>> {
+    fn write(&mut self, value: i64) {
+        self.lock().expect("Output lock is poisoned").write(value)
+    }
+}
+
+/// Values written after the receiver hangs up are dropped.
+impl Output for Sender<i64> {
+    fn write(&mut self, value: i64) {
+        let _ = self.send(value);
+    }
+}
+
+/// Passes output values to a closure.
+pub struct FnOutput<F>(pub F);
+
+impl<F: FnMut(i64)> Output for FnOutput<F> {
+    fn write(&mut self, value: i64) {
+        (self.0)(value)
+    }
+}
@@ -0,0 +1,312 @@
+use super::{InstructionSet, Memory, Mode};
+use std::fmt;
+
+/// How an instruction interprets one of its parameters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Parameter {
+    Position(i64),
+    Immediate(i64),
+    Relative(i64),
+}
+
+impl Parameter {
+    fn new(mode: &Mode, value: i64) -> Self {
+        match mode {
+            Mode::Position => Parameter::Position(value),
+            Mode::Immediate => Parameter::Immediate(value),
+            Mode::Relative => Parameter::Relative(value),
+        }
+    }
+
+    pub fn mode_code(&self) -> i64 {
+        match self {
+            Parameter::Position(_) => Mode::Position.code(),
+            Parameter::Immediate(_) => Mode::Immediate.code(),
+            Parameter::Relative(_) => Mode::Relative.code(),
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        match self {
+            Parameter::Position(value)
+            | Parameter::Immediate(value)
+            | Parameter::Relative(value) => *value,
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parameter::Position(address) => write!(f, "[{}]", address),
+            Parameter::Immediate(value) => write!(f, "{}", value),
+            Parameter::Relative(offset) if *offset < 0 => write!(f, "[rb{}]", offset),
+            Parameter::Relative(offset) => write!(f, "[rb+{}]", offset),
+        }
+    }
+}
+
+/// The mnemonic and parameter layout of an opcode.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Definition {
+    pub opcode: i64,
+    pub mnemonic: String,
+    /// The number of parameters that are read.
+    pub reads: usize,
+    /// Whether the last parameter is an address that is written to.
+    pub writes: bool,
+}
+
+impl Definition {
+    pub fn arity(&self) -> usize {
+        self.reads + self.writes as usize
+    }
+}
+
+/// One decoded instruction, or a word that does not decode to an instruction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Line {
+    Instruction {
+        address: usize,
+        definition: Definition,
+        parameters: Vec<Parameter>,
+    },
+    Data {
+        address: usize,
+        value: i64,
+    },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => *address,
+        }
+    }
+
+    /// The number of memory words the line covers.
+    pub fn size(&self) -> usize {
+        match self {
+            Line::Instruction { parameters, .. } => 1 + parameters.len(),
+            Line::Data { .. } => 1,
+        }
+    }
+
+    /// Encodes the line back into memory words.
+    pub fn words(&self) -> Vec<i64> {
+        match self {
+            Line::Instruction {
+                definition,
+                parameters,
+                ..
+            } => {
+                let mut instruction = definition.opcode;
+                let mut place = 100;
+                for parameter in parameters {
+                    instruction += parameter.mode_code() * place;
+                    place *= 10;
+                }
+
+                let mut words = vec![instruction];
+                words.extend(parameters.iter().map(Parameter::value));
+                words
+            }
+            Line::Data { value, .. } => vec![*value],
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction {
+                definition,
+                parameters,
+                ..
+            } => {
+                write!(f, "{}", definition.mnemonic)?;
+                let (reads, writes) = parameters.split_at(definition.reads);
+                for (index, parameter) in reads.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, parameter)?;
+                }
+                if let Some(target) = writes.first() {
+                    write!(f, " -> {}", target)?;
+                }
+
+                Ok(())
+            }
+            Line::Data { value, .. } => write!(f, "DATA {}", value),
+        }
+    }
+}
+
+/// Decodes the word at `address` with the opcodes of `instruction_set`. Words that are not the
+/// canonical encoding of an instruction that fits inside memory decode as data.
+pub fn decode(instruction_set: &InstructionSet, memory: &Memory, address: usize) -> Line {
+    let value = memory.get(address);
+    let data = Line::Data { address, value };
+    let definition = match instruction_set.get(value % 100) {
+        Some(opcode) => opcode.definition(),
+        None => return data,
+    };
+    if address + definition.arity() >= memory.len() {
+        return data;
+    }
+    let modes = match Mode::modes_from_instruction(value) {
+        Ok(modes) => modes,
+        Err(_) => return data,
+    };
+    // Parameters past the third one are always in position mode.
+    let mode = |index: usize| modes.get(index).copied().unwrap_or(Mode::Position);
+    if definition.writes && mode(definition.reads) == Mode::Immediate {
+        return data;
+    }
+
+    let parameters = (0..definition.arity())
+        .map(|index| Parameter::new(&mode(index), memory.get(address + index + 1)))
+        .collect();
+    let line = Line::Instruction {
+        address,
+        definition,
+        parameters,
+    };
+    if line.words()[0] == value {
+        line
+    } else {
+        data
+    }
+}
+
+/// Decodes memory from the start of the program with a linear sweep.
+pub fn disassemble(instruction_set: &InstructionSet, memory: &Memory) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut address = 0;
+    while address < memory.len() {
+        let line = decode(instruction_set, memory, address);
+        address += line.size();
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Renders a listing with one line per instruction, each prefixed with its address.
+pub fn listing(instruction_set: &InstructionSet, memory: &Memory) -> String {
+    disassemble(instruction_set, memory)
+        .iter()
+        .map(|line| format!("{:04}: {}\n", line.address(), line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, disassemble, listing, Line, Parameter};
+    use crate::intcode::assembler::assemble_with;
+    use crate::intcode::{Effect, InstructionSet, Memory, Opcode};
+
+    #[test]
+    fn test_listing() {
+        let memory = Memory::from(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(
+            "0000: ADD [9], [10] -> [3]
+0004: MUL [3], [11] -> [0]
+0008: HLT
+0009: DATA 30
+0010: DATA 40
+0011: DATA 50
+",
+            listing(&InstructionSet::core(), &memory)
+        );
+    }
+
+    #[test]
+    fn test_modes() {
+        let memory = Memory::from(vec![1002, 4, 3, 4, 33, 109, -3, 21107, 1, 2, 3, 203, 5, 99]);
+        let rendered: Vec<String> = disassemble(&InstructionSet::core(), &memory)
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "MUL [4], 3 -> [4]",
+                "DATA 33",
+                "ARB -3",
+                "LT 1, 2 -> [rb+3]",
+                "IN -> [rb+5]",
+                "HLT"
+            ],
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_data_fallback() {
+        let memory = Memory::from(vec![11101, 1, 2, 3, 199, 42, 1, 1, 2]);
+        assert_eq!(
+            "DATA 11101",
+            decode(&InstructionSet::core(), &memory, 0).to_string()
+        );
+        assert_eq!(
+            "DATA 199",
+            decode(&InstructionSet::core(), &memory, 4).to_string()
+        );
+        assert_eq!(
+            "DATA 42",
+            decode(&InstructionSet::core(), &memory, 5).to_string()
+        );
+        assert_eq!(
+            "DATA 1",
+            decode(&InstructionSet::core(), &memory, 6).to_string()
+        );
+        assert_eq!(
+            Line::Data {
+                address: 0,
+                value: 4
+            },
+            decode(&InstructionSet::core(), &Memory::from(vec![4]), 0)
+        );
+    }
+
+    #[test]
+    fn test_words() {
+        let memory = Memory::from(vec![21101, -1, 7, 3, 104, 5, 1006, 0, 12, 99]);
+        let words: Vec<i64> = disassemble(&InstructionSet::core(), &memory)
+            .iter()
+            .flat_map(|line| line.words())
+            .collect();
+        assert_eq!(memory.to_vec(), words);
+        assert_eq!(
+            Line::Instruction {
+                address: 0,
+                definition: InstructionSet::core()
+                    .get(1)
+                    .expect("ADD is a core opcode")
+                    .definition(),
+                parameters: vec![
+                    Parameter::Immediate(-1),
+                    Parameter::Immediate(7),
+                    Parameter::Relative(3)
+                ],
+            },
+            decode(&InstructionSet::core(), &memory, 0)
+        );
+    }
+
+    #[test]
+    fn test_registered_opcodes() {
+        let mut instruction_set = InstructionSet::core();
+        instruction_set.register(Opcode::new(70, "SUM3", 3, true, |_| Ok(Effect::Next)));
+        let memory = assemble_with(&instruction_set, "SUM3 [rb+1], 2, [3] -> [9]\nHLT")
+            .expect("Could not assemble");
+        assert_eq!(vec![1270, 1, 2, 3, 9, 99], memory.to_vec());
+        assert_eq!(
+            "0000: SUM3 [rb+1], 2, [3] -> [9]\n0005: HLT\n",
+            listing(&instruction_set, &memory)
+        );
+        assert_eq!(
+            "DATA 1270",
+            decode(&InstructionSet::core(), &memory, 0).to_string()
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Property tests that run randomly generated programs. Every run is seeded, so a failure can
+//! be reproduced from the seed in its message.
+
+use super::compiler::{compile, CompiledComputer};
+use super::{Computer, IntcodeError, Memory, State};
+use std::panic::{self, AssertUnwindSafe};
+
+const PROGRAMS: u64 = 2000;
+const BUDGET: u64 = 2000;
+
+/// A xorshift generator, which is plenty for picking program words.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// Mostly well-formed instructions with nearby addresses, mixed with the malformed words that
+/// used to make the interpreter panic.
+fn program(rng: &mut Rng) -> Vec<i64> {
+    let len = 1 + rng.below(48) as usize;
+    let mut words = Vec::with_capacity(len);
+    while words.len() < len {
+        if rng.chance(10) {
+            words.push(value(rng, len));
+            continue;
+        }
+        let opcode = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99, 0, 42][rng.below(12) as usize];
+        let modes: i64 = (0..3)
+            .map(|place| {
+                let mode = if rng.chance(3) {
+                    3
+                } else {
+                    rng.below(3) as i64
+                };
+                mode * 10_i64.pow(place)
+            })
+            .sum();
+        words.push(modes * 100 + opcode);
+        for _ in 0..3 {
+            words.push(value(rng, len));
+        }
+    }
+    words.truncate(len);
+    words
+}
+
+fn value(rng: &mut Rng, len: usize) -> i64 {
+    match rng.below(10) {
+        0 => -(rng.below(len as u64 + 2) as i64),
+        1 => [i64::MIN, i64::MAX, 1 << 40, -(1 << 40), 99][rng.below(5) as usize],
+        2 | 3 => rng.below(200) as i64 - 100,
+        _ => rng.below(len as u64 + 4) as i64,
+    }
+}
+
+/// A computer driven one instruction at a time, by either execution engine.
+enum Engine {
+    Interpreter(Computer),
+    Compiled(CompiledComputer),
+}
+
+impl Engine {
+    fn step(&mut self) -> Result<State, IntcodeError> {
+        match self {
+            Engine::Interpreter(computer) => computer.step(),
+            Engine::Compiled(computer) => computer.step(),
+        }
+    }
+
+    fn push_input(&mut self, value: i64) {
+        match self {
+            Engine::Interpreter(computer) => computer.push_input(value),
+            Engine::Compiled(computer) => computer.push_input(value),
+        }
+    }
+
+    fn set_instruction_limit(&mut self, limit: u64) {
+        match self {
+            Engine::Interpreter(computer) => computer.set_instruction_limit(Some(limit)),
+            Engine::Compiled(computer) => computer.set_instruction_limit(Some(limit)),
+        }
+    }
+
+    fn instructions_executed(&self) -> u64 {
+        match self {
+            Engine::Interpreter(computer) => computer.instructions_executed(),
+            Engine::Compiled(computer) => computer.instructions_executed(),
+        }
+    }
+
+    fn memory(&self) -> &Memory {
+        match self {
+            Engine::Interpreter(computer) => &computer.memory,
+            Engine::Compiled(computer) => computer.memory(),
+        }
+    }
+
+    fn pointer(&self) -> usize {
+        match self {
+            Engine::Interpreter(computer) => computer.pointer(),
+            Engine::Compiled(computer) => computer.computer().pointer(),
+        }
+    }
+}
+
+/// Everything observable about a run.
+#[derive(Debug, PartialEq)]
+struct Run {
+    /// Every state other than `Running`, ending with the one that stopped the run.
+    states: Vec<Result<State, IntcodeError>>,
+    instructions: u64,
+    pointer: usize,
+    memory: Memory,
+}
+
+impl Run {
+    fn last(&self) -> Option<&Result<State, IntcodeError>> {
+        self.states.last()
+    }
+}
+
+/// Runs until the program halts, fails, runs out of inputs or uses up the budget.
+fn run(mut engine: Engine, inputs: &[i64]) -> Run {
+    engine.set_instruction_limit(BUDGET);
+    let mut inputs = inputs.iter();
+    let mut states = vec![];
+    loop {
+        let state = engine.step();
+        match state {
+            Ok(State::Running) => continue,
+            Ok(State::Output(_)) => states.push(state),
+            Ok(State::NeedsInput) => match inputs.next() {
+                Some(value) => engine.push_input(*value),
+                None => {
+                    states.push(state);
+                    break;
+                }
+            },
+            _ => {
+                states.push(state);
+                break;
+            }
+        }
+    }
+
+    Run {
+        states,
+        instructions: engine.instructions_executed(),
+        pointer: engine.pointer(),
+        memory: engine.memory().clone(),
+    }
+}
+
+fn check(seed: u64) {
+    let mut rng = Rng::new(seed);
+    let words = program(&mut rng);
+    let inputs: Vec<i64> = (0..rng.below(4))
+        .map(|_| value(&mut rng, words.len()))
+        .collect();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let reference = {
+            let mut computer = Computer::new(words.clone());
+            computer.set_decode_cache(false);
+            run(Engine::Interpreter(computer), &inputs)
+        };
+        assert!(reference.instructions <= BUDGET);
+        let cached = run(Engine::Interpreter(Computer::new(words.clone())), &inputs);
+        assert_eq!(reference, cached, "The decode cache changed the result");
+        let compiled = run(
+            Engine::Compiled(compile(&words.clone().into()).computer()),
+            &inputs,
+        );
+        assert_eq!(reference, compiled, "The compiler changed the result");
+
+        let mut computer = Computer::new(words.clone());
+        computer.set_loop_detection(true);
+        let detected = run(Engine::Interpreter(computer), &inputs);
+        match detected.last() {
+            // A proven loop must never halt, so it can only end by using up the budget.
+            Some(Err(IntcodeError::InfiniteLoop { .. })) => assert_eq!(
+                Some(&Err(IntcodeError::InstructionLimitExceeded {
+                    limit: BUDGET
+                })),
+                reference.last(),
+                "Loop detection reported a program that stops"
+            ),
+            _ => assert_eq!(reference, detected, "Loop detection changed the result"),
+        }
+    }));
+    if let Err(error) = result {
+        panic!(
+            "Seed {} failed with program {:?} and inputs {:?}: {:?}",
+            seed,
+            words,
+            inputs,
+            error
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| error.downcast_ref::<&str>().copied())
+        );
+    }
+}
+
+#[test]
+fn test_random_programs() {
+    for seed in 0..PROGRAMS {
+        check(seed);
+    }
+}
+
+#[test]
+fn test_runaway_program_stops_at_budget() {
+    // Jumps back to itself forever.
+    let engines = [
+        Engine::Interpreter(Computer::new(vec![1105, 1, 0])),
+        Engine::Compiled(compile(&vec![1105, 1, 0].into()).computer()),
+    ];
+    for engine in IntoIterator::into_iter(engines) {
+        let run = run(engine, &[]);
+        assert_eq!(BUDGET, run.instructions);
+        assert_eq!(
+            vec![Err(IntcodeError::InstructionLimitExceeded {
+                limit: BUDGET
+            })],
+            run.states
+        );
+    }
+}
@@ -0,0 +1,524 @@
+use super::disassembler::Definition;
+use super::{Computer, IntcodeError, Memory, Mode, Modes};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+/// What to do after an instruction has executed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Effect {
+    /// Continue with the next instruction.
+    Next,
+    /// Continue with the instruction at an address.
+    Jump(i64),
+    /// Produce an output value, then continue with the next instruction.
+    Output(i64),
+    /// Wait for input. The instruction is executed again once input is available.
+    NeedsInput,
+    Halt,
+}
+
+/// Gives an opcode handler access to its parameters and the machine state.
+pub struct Operands<'a> {
+    computer: &'a mut Computer,
+    modes: Modes,
+}
+
+impl<'a> Operands<'a> {
+    pub(super) fn new(computer: &'a mut Computer, modes: Modes) -> Self {
+        Self { computer, modes }
+    }
+
+    /// The mode of parameter `index`, counting from 0. Parameters past the third one are always
+    /// in position mode.
+    fn mode(&self, index: usize) -> Mode {
+        self.modes.get(index).copied().unwrap_or(Mode::Position)
+    }
+
+    /// The value of parameter `index`, counting from 0.
+    pub fn read(&self, index: usize) -> Result<i64, IntcodeError> {
+        self.computer.get_value(index + 1, &self.mode(index))
+    }
+
+    /// The address that parameter `index` refers to, for parameters that are written to.
+    pub fn address(&self, index: usize) -> Result<usize, IntcodeError> {
+        self.computer.get_address(index + 1, &self.mode(index))
+    }
+
+    pub fn write(&mut self, address: usize, value: i64) {
+        self.computer.write(address, value);
+    }
+
+    /// Takes the next input value, if there is one.
+    pub fn input(&mut self) -> Option<i64> {
+        self.computer.next_input()
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.computer.pointer
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.computer.relative_base
+    }
+
+    pub fn adjust_relative_base(&mut self, offset: i64) -> Result<(), IntcodeError> {
+        self.computer.relative_base = self
+            .computer
+            .relative_base
+            .checked_add(offset)
+            .ok_or_else(|| self.overflow())?;
+        Ok(())
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.computer.memory
+    }
+
+    /// The error for an arithmetic overflow in the current instruction.
+    pub fn overflow(&self) -> IntcodeError {
+        self.computer.overflow()
+    }
+}
+
+type CustomHandler = dyn Fn(&mut Operands) -> Result<Effect, IntcodeError> + Send + Sync;
+
+/// The operations of the core instruction set. They are executed with a `match`, so that
+/// `Computer::step` can copy them out of the instruction set and the handlers can be inlined.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum Core {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+}
+
+impl Core {
+    const ALL: [Core; 10] = [
+        Core::Add,
+        Core::Multiply,
+        Core::Input,
+        Core::Output,
+        Core::JumpIfTrue,
+        Core::JumpIfFalse,
+        Core::LessThan,
+        Core::Equals,
+        Core::AdjustRelativeBase,
+        Core::Halt,
+    ];
+
+    /// The opcode, mnemonic, number of parameters that are read, and whether the last parameter
+    /// is written to.
+    fn layout(self) -> (i64, &'static str, usize, bool) {
+        match self {
+            Core::Add => (1, "ADD", 2, true),
+            Core::Multiply => (2, "MUL", 2, true),
+            Core::Input => (3, "IN", 0, true),
+            Core::Output => (4, "OUT", 1, false),
+            Core::JumpIfTrue => (5, "JNZ", 2, false),
+            Core::JumpIfFalse => (6, "JZ", 2, false),
+            Core::LessThan => (7, "LT", 2, true),
+            Core::Equals => (8, "EQ", 2, true),
+            Core::AdjustRelativeBase => (9, "ARB", 1, false),
+            Core::Halt => (99, "HLT", 0, false),
+        }
+    }
+
+    pub(super) fn execute(self, operands: &mut Operands) -> Result<Effect, IntcodeError> {
+        match self {
+            Core::Add => add(operands),
+            Core::Multiply => multiply(operands),
+            Core::Input => input(operands),
+            Core::Output => output(operands),
+            Core::JumpIfTrue => jump_if_true(operands),
+            Core::JumpIfFalse => jump_if_false(operands),
+            Core::LessThan => less_than(operands),
+            Core::Equals => equals(operands),
+            Core::AdjustRelativeBase => adjust_relative_base(operands),
+            Core::Halt => halt(operands),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Handler {
+    Core(Core),
+    Custom(Arc<CustomHandler>),
+}
+
+/// What `Computer::step` copies out of the instruction set for an opcode.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Dispatch {
+    pub arity: usize,
+    /// The operation of a core opcode, or `None` for a registered one.
+    pub core: Option<Core>,
+}
+
+/// An opcode, its parameter layout and what it does.
+#[derive(Clone)]
+pub struct Opcode {
+    pub opcode: i64,
+    pub mnemonic: String,
+    /// The number of parameters that are read.
+    pub reads: usize,
+    /// Whether the last parameter is an address that is written to.
+    pub writes: bool,
+    handler: Handler,
+}
+
+impl Opcode {
+    pub fn new<F>(opcode: i64, mnemonic: &str, reads: usize, writes: bool, handler: F) -> Self
+    where
+        F: Fn(&mut Operands) -> Result<Effect, IntcodeError> + Send + Sync + 'static,
+    {
+        Self {
+            opcode,
+            mnemonic: mnemonic.to_string(),
+            reads,
+            writes,
+            handler: Handler::Custom(Arc::new(handler)),
+        }
+    }
+
+    fn from_core(core: Core) -> Self {
+        let (opcode, mnemonic, reads, writes) = core.layout();
+        Self {
+            opcode,
+            mnemonic: mnemonic.to_string(),
+            reads,
+            writes,
+            handler: Handler::Core(core),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.reads + self.writes as usize
+    }
+
+    /// The mnemonic and parameter layout, as used by the disassembler and assembler.
+    pub fn definition(&self) -> Definition {
+        Definition {
+            opcode: self.opcode,
+            mnemonic: self.mnemonic.clone(),
+            reads: self.reads,
+            writes: self.writes,
+        }
+    }
+
+    /// The core operation this opcode performs, or `None` for a registered handler.
+    pub(super) fn core(&self) -> Option<Core> {
+        match self.handler {
+            Handler::Core(core) => Some(core),
+            Handler::Custom(_) => None,
+        }
+    }
+
+    pub(super) fn execute(&self, operands: &mut Operands) -> Result<Effect, IntcodeError> {
+        match &self.handler {
+            Handler::Core(core) => core.execute(operands),
+            Handler::Custom(handler) => handler(operands),
+        }
+    }
+
+    fn dispatch(&self) -> Dispatch {
+        Dispatch {
+            arity: self.arity(),
+            core: self.core(),
+        }
+    }
+}
+
+impl fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Opcode")
+            .field("opcode", &self.opcode)
+            .field("mnemonic", &self.mnemonic)
+            .field("reads", &self.reads)
+            .field("writes", &self.writes)
+            .finish()
+    }
+}
+
+/// The opcodes a `Computer` understands, indexed by the two low digits of an instruction.
+#[derive(Clone, Debug)]
+pub struct InstructionSet {
+    opcodes: Vec<Option<Opcode>>,
+    dispatch: Vec<Option<Dispatch>>,
+}
+
+impl InstructionSet {
+    pub fn empty() -> Self {
+        Self {
+            opcodes: vec![None; 100],
+            dispatch: vec![None; 100],
+        }
+    }
+
+    /// The opcodes of the complete Intcode computer.
+    pub fn core() -> Self {
+        let mut instruction_set = Self::empty();
+        for &core in Core::ALL.iter() {
+            instruction_set.register(Opcode::from_core(core));
+        }
+
+        instruction_set
+    }
+
+    /// Adds an opcode, replacing any opcode with the same number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the opcode is not between 0 and 99, since it could never be decoded.
+    pub fn register(&mut self, opcode: Opcode) {
+        assert!(
+            (0..100).contains(&opcode.opcode),
+            "Opcode {} is not between 0 and 99",
+            opcode.opcode
+        );
+        let index = opcode.opcode as usize;
+        self.dispatch[index] = Some(opcode.dispatch());
+        self.opcodes[index] = Some(opcode);
+    }
+
+    pub fn get(&self, opcode: i64) -> Option<&Opcode> {
+        if (0..100).contains(&opcode) {
+            self.opcodes[opcode as usize].as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Looks up an opcode by its mnemonic, ignoring case.
+    pub fn find(&self, mnemonic: &str) -> Option<&Opcode> {
+        self.opcodes
+            .iter()
+            .flatten()
+            .find(|opcode| opcode.mnemonic.eq_ignore_ascii_case(mnemonic))
+    }
+
+    pub(super) fn dispatch(&self, opcode: i64) -> Option<Dispatch> {
+        if (0..100).contains(&opcode) {
+            self.dispatch[opcode as usize]
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        Self::core()
+    }
+}
+
+/// The core instruction set, shared by every `Computer` that does not register its own opcodes.
+pub(super) fn core() -> Arc<InstructionSet> {
+    static CORE: OnceLock<Arc<InstructionSet>> = OnceLock::new();
+    Arc::clone(CORE.get_or_init(|| Arc::new(InstructionSet::core())))
+}
+
+fn arithmetic<F>(operands: &mut Operands, operation: F) -> Result<Effect, IntcodeError>
+where
+    F: Fn(i64, i64) -> Option<i64>,
+{
+    let address = operands.address(2)?;
+    let result =
+        operation(operands.read(0)?, operands.read(1)?).ok_or_else(|| operands.overflow())?;
+    operands.write(address, result);
+    Ok(Effect::Next)
+}
+
+fn add(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    arithmetic(operands, i64::checked_add)
+}
+
+fn multiply(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    arithmetic(operands, i64::checked_mul)
+}
+
+fn less_than(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    arithmetic(operands, |left, right| Some((left < right) as i64))
+}
+
+fn equals(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    arithmetic(operands, |left, right| Some((left == right) as i64))
+}
+
+fn input(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    let address = operands.address(0)?;
+    match operands.input() {
+        Some(value) => {
+            operands.write(address, value);
+            Ok(Effect::Next)
+        }
+        None => Ok(Effect::NeedsInput),
+    }
+}
+
+fn output(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    Ok(Effect::Output(operands.read(0)?))
+}
+
+fn jump(operands: &mut Operands, test: bool) -> Result<Effect, IntcodeError> {
+    if (operands.read(0)? != 0) == test {
+        Ok(Effect::Jump(operands.read(1)?))
+    } else {
+        Ok(Effect::Next)
+    }
+}
+
+fn jump_if_true(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    jump(operands, true)
+}
+
+fn jump_if_false(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    jump(operands, false)
+}
+
+fn adjust_relative_base(operands: &mut Operands) -> Result<Effect, IntcodeError> {
+    let offset = operands.read(0)?;
+    operands.adjust_relative_base(offset)?;
+    Ok(Effect::Next)
+}
+
+fn halt(_: &mut Operands) -> Result<Effect, IntcodeError> {
+    Ok(Effect::Halt)
+}
+
+/// Configures a `Computer`, including any opcodes beyond the core instruction set.
+pub struct Builder {
+    computer: Computer,
+    instruction_set: InstructionSet,
+}
+
+impl Builder {
+    pub(super) fn new(computer: Computer) -> Self {
+        Self {
+            computer,
+            instruction_set: InstructionSet::core(),
+        }
+    }
+
+    /// Replaces the whole instruction set, for example with `InstructionSet::empty()`.
+    pub fn instruction_set(mut self, instruction_set: InstructionSet) -> Self {
+        self.instruction_set = instruction_set;
+        self
+    }
+
+    /// Adds an opcode, replacing any opcode with the same number.
+    pub fn opcode(mut self, opcode: Opcode) -> Self {
+        self.instruction_set.register(opcode);
+        self
+    }
+
+    pub fn input<I: super::Input + Send + 'static>(mut self, input: I) -> Self {
+        self.computer.set_input(input);
+        self
+    }
+
+    pub fn output<O: super::Output + Send + 'static>(mut self, output: O) -> Self {
+        self.computer.set_output(output);
+        self
+    }
+
+    pub fn decode_cache(mut self, enabled: bool) -> Self {
+        self.computer.set_decode_cache(enabled);
+        self
+    }
+
+    pub fn build(mut self) -> Computer {
+        self.computer.instruction_set = Arc::new(self.instruction_set);
+        self.computer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Effect, InstructionSet, Opcode};
+    use crate::intcode::{Computer, IntcodeError, State};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_custom_opcodes() {
+        let printed = Arc::new(Mutex::new(vec![]));
+        let exit_code = Arc::new(Mutex::new(None));
+        let (log, code) = (Arc::clone(&printed), Arc::clone(&exit_code));
+        let mut computer = Computer::builder(vec![1150, 7, 160, 7, 10, 4, 10, 1198, 3, 0, 0])
+            // Debug print: records its parameter without producing output.
+            .opcode(Opcode::new(50, "DBG", 1, false, move |operands| {
+                log.lock()
+                    .expect("Lock is poisoned")
+                    .push(operands.read(0)?);
+                Ok(Effect::Next)
+            }))
+            // Host call: writes the square of its first parameter to its second one.
+            .opcode(Opcode::new(60, "SQR", 1, true, |operands| {
+                let value = operands.read(0)?;
+                let address = operands.address(1)?;
+                let square = value
+                    .checked_mul(value)
+                    .ok_or_else(|| operands.overflow())?;
+                operands.write(address, square);
+                Ok(Effect::Next)
+            }))
+            // Halt with an exit code.
+            .opcode(Opcode::new(98, "HCF", 1, false, move |operands| {
+                *code.lock().expect("Lock is poisoned") = Some(operands.read(0)?);
+                Ok(Effect::Halt)
+            }))
+            .build();
+        assert_eq!(Ok(Some(49)), computer.execute(None));
+        assert_eq!(vec![7], *printed.lock().expect("Lock is poisoned"));
+        assert_eq!(Some(3), *exit_code.lock().expect("Lock is poisoned"));
+        assert_eq!(
+            Some("SQR"),
+            computer
+                .instruction_set()
+                .get(60)
+                .map(|opcode| opcode.mnemonic.as_str())
+        );
+    }
+
+    #[test]
+    fn test_replace_core_opcode() {
+        let mut computer = Computer::builder(vec![1101, i64::MAX, 1, 7, 4, 7, 99, 0])
+            .opcode(Opcode::new(1, "ADD", 2, true, |operands| {
+                let address = operands.address(2)?;
+                let sum = operands.read(0)?.saturating_add(operands.read(1)?);
+                operands.write(address, sum);
+                Ok(Effect::Next)
+            }))
+            .build();
+        assert_eq!(Ok(Some(i64::MAX)), computer.execute(None));
+
+        let mut computer = Computer::builder(vec![1101, 1, 1, 0, 99])
+            .instruction_set(InstructionSet::empty())
+            .build();
+        assert_eq!(
+            Err(IntcodeError::UnknownOpcode {
+                opcode: 1,
+                address: 0
+            }),
+            computer.execute(None)
+        );
+    }
+
+    #[test]
+    fn test_wide_opcode() {
+        // Parameters after the third one are always in position mode.
+        let mut computer = Computer::builder(vec![11170, 1, 2, 3, 9, 4, 9, 99, 0, 0])
+            .opcode(Opcode::new(70, "SUM3", 3, true, |operands| {
+                let sum = operands.read(0)? + operands.read(1)? + operands.read(2)?;
+                let address = operands.address(3)?;
+                operands.write(address, sum);
+                Ok(Effect::Next)
+            }))
+            .build();
+        assert_eq!(Ok(State::Output(6)), computer.resume());
+    }
+}
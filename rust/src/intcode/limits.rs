@@ -0,0 +1,261 @@
+use super::{Computer, IntcodeError, Memory};
+use std::time::Instant;
+
+/// The deadline is only checked every this many instructions, since reading the clock costs
+/// more than most instructions. A power of two, so that the check is a mask.
+const DEADLINE_INTERVAL: u64 = 1024;
+
+/// Mixes an address and the value stored there into a hash contribution. Zero words contribute
+/// nothing, so memory that was never written does not need to be visited.
+fn word_hash(address: usize, value: i64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    let mut hash = (address as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ value as u64;
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Finds a repeated machine state among the states that follow a jump, using Brent's cycle
+/// detection so that only one state is kept however long the program runs. Without input or
+/// output, each of these states determines the next one, so the states repeat from the first
+/// repeat onwards and the saved state is eventually met again.
+#[derive(Clone, Debug)]
+pub(super) struct LoopDetector {
+    /// The sum of the hashes of every memory word, kept up to date as the program writes.
+    memory_hash: u64,
+    /// The pointer, relative base, memory hash and memory that later states are compared with.
+    /// Memory is copy-on-write, so the copy only costs the pages written since it was taken.
+    saved: Option<(usize, i64, u64, Memory)>,
+    /// The number of states to compare with the saved state before saving a new one. It doubles
+    /// every time, so that the saved state ends up inside any cycle, and a cycle of any length
+    /// fits between two saves.
+    power: u64,
+    /// The number of states compared with the saved state so far.
+    length: u64,
+}
+
+impl LoopDetector {
+    fn new(memory: &Memory) -> Self {
+        let dense = memory.to_vec().into_iter().enumerate();
+        let memory_hash = dense
+            .chain(memory.sparse_words())
+            .fold(0, |hash: u64, (address, value)| {
+                hash.wrapping_add(word_hash(address, value))
+            });
+
+        Self {
+            memory_hash,
+            saved: None,
+            power: 1,
+            length: 1,
+        }
+    }
+
+    pub(super) fn update(&mut self, address: usize, old: i64, new: i64) {
+        self.memory_hash = self
+            .memory_hash
+            .wrapping_sub(word_hash(address, old))
+            .wrapping_add(word_hash(address, new));
+    }
+
+    /// Whether the program has been in this state before. Called with the state after each
+    /// jump. Matching hashes are confirmed by comparing memory, so hash collisions are never
+    /// reported.
+    fn repeated(&mut self, pointer: usize, relative_base: i64, memory: &Memory) -> bool {
+        let hash = self.memory_hash;
+        if let Some((saved_pointer, saved_base, saved_hash, saved_memory)) = &self.saved {
+            if (*saved_pointer, *saved_base, *saved_hash) == (pointer, relative_base, hash)
+                && saved_memory == memory
+            {
+                return true;
+            }
+        }
+        if self.length == self.power {
+            self.saved = Some((pointer, relative_base, hash, memory.clone()));
+            self.power *= 2;
+            self.length = 0;
+        }
+        self.length += 1;
+
+        false
+    }
+
+    /// Forgets the saved state, because input or output happened.
+    pub(super) fn reset(&mut self) {
+        self.saved = None;
+        self.power = 1;
+        self.length = 1;
+    }
+}
+
+impl Computer {
+    /// Stops execution with `IntcodeError::InstructionLimitExceeded` once this many
+    /// instructions have executed. The count starts again whenever `execute` is called.
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.instruction_limit = limit;
+    }
+
+    /// Stops execution with `IntcodeError::DeadlineExceeded` once `deadline` has passed. The
+    /// clock is checked every 1024 instructions.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Stops execution with `IntcodeError::InfiniteLoop` when the program takes a jump into a
+    /// state it has been in before: the same pointer, relative base and memory, with no input
+    /// or output in between and no queued input. Memory is compared by a hash that is updated as
+    /// the program writes, and a matching hash is confirmed by comparing with a copy of memory.
+    /// Turn detection on again after patching `memory` directly, since the hash is not updated.
+    /// Detection takes constant memory, and reports a loop within a few times the number of
+    /// jumps it takes the program to repeat a state.
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.loop_detector = if enabled {
+            Some(LoopDetector::new(&self.memory))
+        } else {
+            None
+        };
+    }
+
+    /// The number of instructions executed since the computer was created or `execute` was
+    /// last called.
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Called before each instruction executes.
+    pub(super) fn check_limits(&self) -> Result<(), IntcodeError> {
+        if let Some(limit) = self.instruction_limit {
+            if self.instructions >= limit {
+                return Err(IntcodeError::InstructionLimitExceeded { limit });
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if self.instructions & (DEADLINE_INTERVAL - 1) == 0 && Instant::now() >= deadline {
+                return Err(IntcodeError::DeadlineExceeded {
+                    instructions: self.instructions,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Called after a jump, with the pointer on the jump target.
+    pub(super) fn check_loop(&mut self) -> Result<(), IntcodeError> {
+        let (pointer, relative_base) = (self.pointer, self.relative_base);
+        let pending_input = !self.inputs.is_empty();
+        match self.loop_detector.as_mut() {
+            Some(detector) if !pending_input => {
+                if detector.repeated(pointer, relative_base, &self.memory) {
+                    Err(IntcodeError::InfiniteLoop { pointer })
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts a fresh run for `execute`.
+    pub(super) fn reset_limits(&mut self) {
+        self.instructions = 0;
+        if let Some(detector) = self.loop_detector.as_mut() {
+            detector.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::{Computer, IntcodeError, State};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_instruction_limit() {
+        let mut computer = Computer::new(vec![1105, 1, 0]);
+        computer.set_instruction_limit(Some(100));
+        assert_eq!(
+            Err(IntcodeError::InstructionLimitExceeded { limit: 100 }),
+            computer.execute(None)
+        );
+        assert_eq!(100, computer.instructions_executed());
+
+        let mut computer = Computer::new(vec![1101, 1, 2, 7, 4, 7, 99, 0]);
+        computer.set_instruction_limit(Some(3));
+        assert_eq!(Ok(Some(3)), computer.execute(None));
+        assert_eq!(Ok(Some(3)), computer.execute(None));
+        assert_eq!(3, computer.instructions_executed());
+    }
+
+    #[test]
+    fn test_deadline() {
+        let mut computer = Computer::new(vec![1105, 1, 0]);
+        computer.set_deadline(Some(Instant::now() + Duration::from_millis(20)));
+        match computer.execute(None) {
+            Err(IntcodeError::DeadlineExceeded { instructions }) => assert!(instructions > 0),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_loop_detection() {
+        let mut computer = Computer::new(vec![1105, 1, 0]);
+        computer.set_loop_detection(true);
+        assert_eq!(
+            Err(IntcodeError::InfiniteLoop { pointer: 0 }),
+            computer.execute(None)
+        );
+
+        // Counts down to zero, so every jump lands in a new state.
+        let memory = assemble(
+            "
+        loop:   ADD [counter], -1 -> [counter]
+                JNZ [counter], loop
+                OUT 7
+        spin:   JNZ 1, spin
+        counter: DATA 1000
+            ",
+        )
+        .expect("Could not assemble");
+        let mut computer = Computer::new(memory);
+        computer.set_loop_detection(true);
+        assert_eq!(Ok(State::Output(7)), computer.resume());
+        assert_eq!(
+            Err(IntcodeError::InfiniteLoop { pointer: 9 }),
+            computer.resume()
+        );
+
+        // Flips a flag on every iteration, so a state only repeats every other jump.
+        let memory = assemble(
+            "
+        loop:   EQ [flag], 0 -> [flag]
+                JNZ 1, loop
+        flag:   DATA 0
+            ",
+        )
+        .expect("Could not assemble");
+        let mut computer = Computer::new(memory);
+        computer.set_loop_detection(true);
+        assert_eq!(
+            Err(IntcodeError::InfiniteLoop { pointer: 0 }),
+            computer.execute(None)
+        );
+    }
+
+    #[test]
+    fn test_loop_detection_ignores_io() {
+        // Echoes its input forever, which is not a loop while input keeps arriving.
+        let mut computer = Computer::new(vec![3, 7, 4, 7, 1105, 1, 0, 0]);
+        computer.set_loop_detection(true);
+        for value in 0..3 {
+            computer.push_input(value % 2);
+            assert_eq!(Ok(State::Output(value % 2)), computer.resume());
+        }
+        assert_eq!(Ok(State::NeedsInput), computer.resume());
+    }
+}
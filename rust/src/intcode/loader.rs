@@ -0,0 +1,295 @@
+use super::{IntcodeError, Memory};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8; 2] = b"\x1f\x8b";
+const LEB128_MAGIC: &[u8; 4] = b"ICL1";
+const FIXED_WIDTH_MAGIC: &[u8; 4] = b"ICW1";
+
+/// How a program is stored.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Comma-separated decimal values, as in puzzle inputs.
+    Text,
+    /// Signed LEB128 values after an `ICL1` header.
+    Leb128,
+    /// Little-endian 64-bit values after an `ICW1` header.
+    FixedWidth,
+}
+
+/// Loads a program in any `Format`, optionally gzip-compressed. The format is detected from
+/// the first bytes of the input. Text that cannot be parsed is reported as an
+/// `io::ErrorKind::InvalidData` error wrapping an `IntcodeError::Parse`.
+pub fn load<R: Read>(mut reader: R) -> io::Result<Memory> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    if bytes.starts_with(GZIP_MAGIC) {
+        let mut decompressed = vec![];
+        GzDecoder::new(&bytes[..]).read_to_end(&mut decompressed)?;
+        bytes = decompressed;
+    }
+
+    if let Some(words) = bytes.strip_prefix(LEB128_MAGIC) {
+        decode_leb128(words)
+    } else if let Some(words) = bytes.strip_prefix(FIXED_WIDTH_MAGIC) {
+        decode_fixed_width(words)
+    } else {
+        parse(&bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+pub fn load_file<P: AsRef<Path>>(path: P) -> io::Result<Memory> {
+    load(File::open(path)?)
+}
+
+/// Parses a program in the text format. Values can be separated by commas, whitespace or
+/// both, and a trailing comma is allowed. `#` starts a comment that runs to the end of the line.
+pub fn parse(input: &[u8]) -> Result<Memory, IntcodeError> {
+    let mut memory = Memory::new();
+    let mut address = 0;
+    let mut after_comma = false;
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut position = 0;
+    let error = |offset: usize, line: usize, line_start: usize, value: &[u8]| IntcodeError::Parse {
+        offset,
+        line,
+        column: offset - line_start + 1,
+        value: String::from_utf8_lossy(value).to_string(),
+    };
+
+    while position < input.len() {
+        match input[position] {
+            b'\n' => {
+                position += 1;
+                line += 1;
+                line_start = position;
+            }
+            b'#' => {
+                while position < input.len() && input[position] != b'\n' {
+                    position += 1;
+                }
+            }
+            b',' => {
+                if address == 0 || after_comma {
+                    return Err(error(position, line, line_start, b""));
+                }
+                after_comma = true;
+                position += 1;
+            }
+            byte if byte.is_ascii_whitespace() => position += 1,
+            _ => {
+                let start = position;
+                while position < input.len()
+                    && !input[position].is_ascii_whitespace()
+                    && !matches!(input[position], b',' | b'#')
+                {
+                    position += 1;
+                }
+                let token = &input[start..position];
+                let value = std::str::from_utf8(token)
+                    .ok()
+                    .and_then(|token| token.parse().ok())
+                    .ok_or_else(|| error(start, line, line_start, token))?;
+                memory.set(address, value);
+                address += 1;
+                after_comma = false;
+            }
+        }
+    }
+
+    Ok(memory)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn decode_leb128(bytes: &[u8]) -> io::Result<Memory> {
+    let mut memory = Memory::new();
+    let mut address = 0;
+    let mut position = 0;
+    while position < bytes.len() {
+        let start = position;
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *bytes.get(position).ok_or_else(|| {
+                invalid_data(format!("Truncated LEB128 value at byte offset {}", start))
+            })?;
+            position += 1;
+            // The tenth byte only holds bit 63, so the rest of it must sign-extend that bit.
+            if shift == 63 && byte != 0x00 && byte != 0x7f {
+                return Err(invalid_data(format!(
+                    "LEB128 value at byte offset {} does not fit in 64 bits",
+                    start
+                )));
+            }
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                break;
+            }
+        }
+        memory.set(address, value);
+        address += 1;
+    }
+
+    Ok(memory)
+}
+
+fn decode_fixed_width(bytes: &[u8]) -> io::Result<Memory> {
+    let trailing = bytes.len() % 8;
+    if trailing != 0 {
+        return Err(invalid_data(format!(
+            "Fixed width program has {} trailing bytes",
+            trailing
+        )));
+    }
+    let mut memory = Memory::new();
+    for (address, word) in bytes.chunks(8).enumerate() {
+        let mut value = [0; 8];
+        value.copy_from_slice(word);
+        memory.set(address, i64::from_le_bytes(value));
+    }
+
+    Ok(memory)
+}
+
+/// Writes the contiguous region of `memory` starting at address 0. Words that only exist in
+/// the sparse region are not saved; use a `Snapshot` to keep them.
+pub fn save<W: Write>(memory: &Memory, format: Format, mut writer: W) -> io::Result<()> {
+    let words = memory.to_vec();
+    match format {
+        Format::Text => {
+            let text: Vec<String> = words.iter().map(i64::to_string).collect();
+            writeln!(writer, "{}", text.join(","))?;
+        }
+        Format::Leb128 => {
+            writer.write_all(LEB128_MAGIC)?;
+            for mut value in words {
+                loop {
+                    let byte = (value & 0x7f) as u8;
+                    value >>= 7;
+                    let done =
+                        (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+                    if done {
+                        writer.write_all(&[byte])?;
+                        break;
+                    }
+                    writer.write_all(&[byte | 0x80])?;
+                }
+            }
+        }
+        Format::FixedWidth => {
+            writer.write_all(FIXED_WIDTH_MAGIC)?;
+            for value in words {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+/// Like `save`, but gzip-compresses the output.
+pub fn save_gzip<W: Write>(memory: &Memory, format: Format, writer: W) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    save(memory, format, &mut encoder)?;
+    encoder.finish()?.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, parse, save, save_gzip, Format};
+    use crate::intcode::{IntcodeError, Memory};
+
+    #[test]
+    fn test_parse() {
+        let expected = vec![1, 9, 10, 3, -99];
+        for text in [
+            "1,9,10,3,-99",
+            "1,9,10,3,-99,\n",
+            "1\n9\n10\n3\n-99\n",
+            "# Adds two numbers\n1, 9, 10, 3, # ADD [9], [10] -> [3]\n-99",
+        ]
+        .iter()
+        {
+            let memory = parse(text.as_bytes()).expect("Could not parse");
+            assert_eq!(expected, memory.to_vec(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Err(IntcodeError::Parse {
+                offset: 12,
+                line: 2,
+                column: 4,
+                value: "1O".to_string()
+            }),
+            parse(b"1, 2, 3,\n4, 1O, 6")
+        );
+        assert_eq!(
+            Err(IntcodeError::Parse {
+                offset: 2,
+                line: 1,
+                column: 3,
+                value: "".to_string()
+            }),
+            parse(b"1,,2")
+        );
+        assert!(parse(b",1").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let memory = Memory::from(vec![
+            1,
+            0,
+            -1,
+            63,
+            64,
+            -64,
+            -65,
+            1 << 40,
+            i64::MIN,
+            i64::MAX,
+        ]);
+        for format in [Format::Text, Format::Leb128, Format::FixedWidth].iter() {
+            let mut bytes = vec![];
+            save(&memory, *format, &mut bytes).expect("Could not save");
+            assert_eq!(memory, load(&bytes[..]).expect("Could not load"));
+
+            let mut compressed = vec![];
+            save_gzip(&memory, *format, &mut compressed).expect("Could not save");
+            assert_eq!(memory, load(&compressed[..]).expect("Could not load"));
+        }
+    }
+
+    #[test]
+    fn test_binary_encodings() {
+        let mut bytes = vec![];
+        save(&Memory::from(vec![2, -2, 300]), Format::Leb128, &mut bytes).expect("Could not save");
+        assert_eq!(b"ICL1\x02\x7e\xac\x02".to_vec(), bytes);
+
+        assert!(load(&b"ICL1\x80"[..]).is_err());
+        let overlong = b"ICL1\xff\xff\xff\xff\xff\xff\xff\xff\xff\x05";
+        assert!(load(&overlong[..]).is_err());
+        let minus_one = b"ICL1\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f";
+        assert_eq!(
+            vec![-1],
+            load(&minus_one[..]).expect("Could not load").to_vec()
+        );
+        assert!(load(&b"ICW1\x01\x00"[..]).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+/// Addresses below this limit are stored contiguously; anything above lives in sparse pages.
+const DENSE_LIMIT: usize = 1 << 20;
+
+/// Pages are shared between clones until one of them writes to the page.
+type Page = Arc<[i64; PAGE_SIZE]>;
+
+/// Intcode memory that grows on demand. Every address that has not been written reads as zero.
+///
+/// Cloning is cheap: memory is stored in copy-on-write pages, so clones only copy the pages
+/// they write to.
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    pages: Vec<Page>,
+    len: usize,
+    extent: usize,
+    sparse: HashMap<usize, Page>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, address: usize) -> i64 {
+        let page = if address < DENSE_LIMIT {
+            self.pages.get(address >> PAGE_BITS)
+        } else {
+            self.sparse.get(&(address >> PAGE_BITS))
+        };
+        page.map_or(0, |page| page[address & (PAGE_SIZE - 1)])
+    }
+
+    pub fn set(&mut self, address: usize, value: i64) {
+        let index = address >> PAGE_BITS;
+        // The contiguous region follows the loaded program past the dense limit, so that large
+        // programs are saved in full, but far writes stay out of it.
+        if address < DENSE_LIMIT || address <= self.len {
+            self.len = self.len.max(address + 1);
+        }
+        self.extent = self.extent.max(address + 1);
+        let page = if address < DENSE_LIMIT {
+            if index >= self.pages.len() {
+                self.pages
+                    .resize_with(index + 1, || Arc::new([0; PAGE_SIZE]));
+            }
+            &mut self.pages[index]
+        } else {
+            self.sparse
+                .entry(index)
+                .or_insert_with(|| Arc::new([0; PAGE_SIZE]))
+        };
+        Arc::make_mut(page)[address & (PAGE_SIZE - 1)] = value;
+    }
+
+    /// The length of the contiguous region starting at address 0.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// One past the highest address that has been written, including the sparse region.
+    pub fn extent(&self) -> usize {
+        self.extent
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies the contiguous region starting at address 0, which includes the loaded program.
+    pub fn to_vec(&self) -> Vec<i64> {
+        (0..self.len).map(|address| self.get(address)).collect()
+    }
+
+    /// The non-zero words stored outside of the contiguous region, ordered by address.
+    pub fn sparse_words(&self) -> Vec<(usize, i64)> {
+        self.sparse_words_from(self.len)
+    }
+
+    fn sparse_words_from(&self, start: usize) -> Vec<(usize, i64)> {
+        let mut indices: Vec<&usize> = self.sparse.keys().collect();
+        indices.sort();
+        indices
+            .into_iter()
+            .flat_map(|index| {
+                let page = &self.sparse[index];
+                (0..PAGE_SIZE).filter_map(move |offset| {
+                    let value = page[offset];
+                    let address = (index << PAGE_BITS) + offset;
+                    if value == 0 || address < start {
+                        None
+                    } else {
+                        Some((address, value))
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && (0..self.pages.len().max(other.pages.len()))
+                .all(|index| self.pages.get(index) == other.pages.get(index))
+            && self.sparse_words_from(0) == other.sparse_words_from(0)
+    }
+}
+
+impl From<Vec<i64>> for Memory {
+    fn from(words: Vec<i64>) -> Self {
+        let mut memory = Self::new();
+        for (address, value) in words.into_iter().enumerate() {
+            memory.set(address, value);
+        }
+
+        memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Memory, DENSE_LIMIT, PAGE_SIZE};
+
+    #[test]
+    fn test_read_past_end() {
+        let memory = Memory::from(vec![1, 2, 3]);
+        assert_eq!(3, memory.get(2));
+        assert_eq!(0, memory.get(3));
+        assert_eq!(0, memory.get(1_000_000_000));
+        assert_eq!(3, memory.len());
+    }
+
+    #[test]
+    fn test_write_grows_dense_region() {
+        let mut memory = Memory::from(vec![1, 2, 3]);
+        memory.set(5, 7);
+        assert_eq!(vec![1, 2, 3, 0, 0, 7], memory.to_vec());
+        memory.set(PAGE_SIZE * 3, 8);
+        assert_eq!(PAGE_SIZE * 3 + 1, memory.len());
+        assert_eq!(8, memory.get(PAGE_SIZE * 3));
+    }
+
+    #[test]
+    fn test_write_far_address_is_sparse() {
+        let mut memory = Memory::new();
+        memory.set(1_000_000_000, 42);
+        memory.set(DENSE_LIMIT, 9);
+        assert_eq!(42, memory.get(1_000_000_000));
+        assert_eq!(0, memory.get(1_000_000_001));
+        assert_eq!(9, memory.get(DENSE_LIMIT));
+        assert!(memory.is_empty());
+        assert_eq!(1_000_000_001, memory.extent());
+        assert_eq!(
+            vec![(DENSE_LIMIT, 9), (1_000_000_000, 42)],
+            memory.sparse_words()
+        );
+    }
+
+    #[test]
+    fn test_large_program_is_contiguous() {
+        let words: Vec<i64> = (0..DENSE_LIMIT as i64 + 10).collect();
+        let mut memory = Memory::from(words.clone());
+        assert_eq!(DENSE_LIMIT + 10, memory.len());
+        assert_eq!(words, memory.to_vec());
+        assert!(memory.sparse_words().is_empty());
+        memory.set(DENSE_LIMIT * 2, 5);
+        assert_eq!(DENSE_LIMIT + 10, memory.len());
+        assert_eq!(vec![(DENSE_LIMIT * 2, 5)], memory.sparse_words());
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut memory = Memory::from(vec![1, 2, 3]);
+        memory.set(1_000_000_000, 4);
+        let mut clone = memory.clone();
+        assert_eq!(memory, clone);
+        clone.set(1, 20);
+        clone.set(1_000_000_000, 40);
+        assert_eq!(vec![1, 2, 3], memory.to_vec());
+        assert_eq!(4, memory.get(1_000_000_000));
+        assert_eq!(vec![1, 20, 3], clone.to_vec());
+        assert_eq!(40, clone.get(1_000_000_000));
+        assert_ne!(memory, clone);
+    }
+}
@@ -0,0 +1,407 @@
+use super::{Computer, IntcodeError, Memory};
+use std::collections::VecDeque;
+use std::thread;
+
+/// The value that machines read when their packet queue is empty.
+const NO_PACKET: i64 = -1;
+
+/// How many times in a row the monitor may redirect a packet to another address that no
+/// machine has, so that a monitor that keeps doing so cannot hang a round.
+const MAX_REDIRECTS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// What the network should do after consulting its monitor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Continue,
+    Send(Packet),
+    Stop,
+}
+
+/// Watches a network from outside its machines.
+pub trait Monitor {
+    /// Called for each packet sent to an address that no machine has, including packets the
+    /// monitor sends itself, up to `MAX_REDIRECTS` times in a row before the network stops.
+    fn receive(&mut self, packet: Packet) -> Action;
+
+    /// Called when a whole round passes without any machine sending or receiving a packet.
+    fn idle(&mut self) -> Action;
+}
+
+/// Remembers the last packet sent to its address and resends it to machine 0 whenever the
+/// network goes idle. Stops the network once it sends the same Y value twice in a row.
+#[derive(Clone, Debug, Default)]
+pub struct Nat {
+    pub address: i64,
+    /// The first packet the NAT received.
+    pub first_packet: Option<Packet>,
+    last_packet: Option<Packet>,
+    last_sent_y: Option<i64>,
+    /// The Y value that was sent twice in a row.
+    pub repeated_y: Option<i64>,
+}
+
+impl Nat {
+    pub fn new(address: i64) -> Self {
+        Self {
+            address,
+            ..Self::default()
+        }
+    }
+}
+
+impl Monitor for Nat {
+    fn receive(&mut self, packet: Packet) -> Action {
+        if packet.destination == self.address {
+            self.first_packet.get_or_insert(packet);
+            self.last_packet = Some(packet);
+        }
+
+        Action::Continue
+    }
+
+    fn idle(&mut self) -> Action {
+        let packet = match self.last_packet {
+            Some(packet) => packet,
+            None => return Action::Continue,
+        };
+        if self.last_sent_y == Some(packet.y) {
+            self.repeated_y = Some(packet.y);
+            return Action::Stop;
+        }
+        self.last_sent_y = Some(packet.y);
+
+        Action::Send(Packet {
+            destination: 0,
+            ..packet
+        })
+    }
+}
+
+/// How the machines of a network are run within a round.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Scheduler {
+    /// Run every machine on the current thread, in address order.
+    SingleThreaded,
+    /// Split the machines across this many threads. Packets are still routed in address order
+    /// between rounds, so the results match `SingleThreaded`.
+    Threads(usize),
+}
+
+struct Node {
+    computer: Computer,
+    queue: VecDeque<Packet>,
+    /// Output values that do not form a whole packet yet.
+    partial: Vec<i64>,
+}
+
+impl Node {
+    /// Feeds the node its queued packets, or `NO_PACKET` if there are none, and runs it until
+    /// it waits for more input.
+    fn run(&mut self) -> Result<(), IntcodeError> {
+        if self.queue.is_empty() {
+            self.computer.push_input(NO_PACKET);
+        }
+        for packet in self.queue.drain(..) {
+            self.computer.push_input(packet.x);
+            self.computer.push_input(packet.y);
+        }
+        self.computer.run()?;
+        self.partial.extend(self.computer.take_outputs());
+
+        Ok(())
+    }
+
+    fn take_packets(&mut self) -> Vec<Packet> {
+        let whole = self.partial.len() / 3 * 3;
+        self.partial
+            .drain(..whole)
+            .collect::<Vec<i64>>()
+            .chunks(3)
+            .map(|values| Packet {
+                destination: values[0],
+                x: values[1],
+                y: values[2],
+            })
+            .collect()
+    }
+}
+
+/// Machines running the same program that exchange packets of `destination, x, y` outputs.
+/// Each machine first reads its own address, then reads the X and Y values of the packets sent
+/// to it, or `-1` when it has none.
+pub struct Network {
+    nodes: Vec<Node>,
+    scheduler: Scheduler,
+}
+
+impl Network {
+    pub fn new(program: &Memory, size: usize) -> Self {
+        let nodes = (0..size)
+            .map(|address| {
+                let mut computer = Computer::new(program.clone());
+                computer.push_input(address as i64);
+                Node {
+                    computer,
+                    queue: VecDeque::new(),
+                    partial: vec![],
+                }
+            })
+            .collect();
+
+        Self {
+            nodes,
+            scheduler: Scheduler::SingleThreaded,
+        }
+    }
+
+    pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+    }
+
+    /// Queues a packet for a machine. Returns it if no machine has its destination.
+    pub fn send(&mut self, packet: Packet) -> Result<(), Packet> {
+        match self.node_index(packet.destination) {
+            Some(index) => {
+                self.nodes[index].queue.push_back(packet);
+                Ok(())
+            }
+            None => Err(packet),
+        }
+    }
+
+    /// Runs rounds until the monitor stops the network, or the network goes idle and the
+    /// monitor has nothing to send.
+    pub fn run<M: Monitor>(&mut self, monitor: &mut M) -> Result<(), IntcodeError> {
+        while self.round(monitor)? {}
+        Ok(())
+    }
+
+    /// Runs every machine once until it waits for input, then routes the packets they sent.
+    /// Returns whether the network should keep running.
+    pub fn round<M: Monitor>(&mut self, monitor: &mut M) -> Result<bool, IntcodeError> {
+        let received = self.nodes.iter().any(|node| !node.queue.is_empty());
+        match self.scheduler {
+            Scheduler::SingleThreaded => {
+                for node in self.nodes.iter_mut() {
+                    node.run()?;
+                }
+            }
+            Scheduler::Threads(threads) => {
+                let chunk_size = self.nodes.len().div_ceil(threads.max(1));
+                thread::scope(|scope| {
+                    let handles: Vec<_> = self
+                        .nodes
+                        .chunks_mut(chunk_size.max(1))
+                        .map(|chunk| scope.spawn(move || chunk.iter_mut().try_for_each(Node::run)))
+                        .collect();
+                    handles
+                        .into_iter()
+                        .try_for_each(|handle| handle.join().expect("Network thread panicked"))
+                })?;
+            }
+        }
+
+        let packets: Vec<Packet> = self
+            .nodes
+            .iter_mut()
+            .flat_map(|node| node.take_packets())
+            .collect();
+        let sent = !packets.is_empty();
+        for packet in packets {
+            if let Err(packet) = self.send(packet) {
+                if !self.apply(monitor.receive(packet), monitor) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        if received || sent {
+            return Ok(true);
+        }
+        match monitor.idle() {
+            Action::Continue => Ok(false),
+            action => Ok(self.apply(action, monitor)),
+        }
+    }
+
+    /// Carries out an action of the monitor. Returns whether the network should keep running.
+    fn apply<M: Monitor>(&mut self, mut action: Action, monitor: &mut M) -> bool {
+        let mut redirects = 0;
+        loop {
+            match action {
+                Action::Continue => return true,
+                Action::Send(packet) => match self.send(packet) {
+                    Ok(()) => return true,
+                    Err(packet) if redirects < MAX_REDIRECTS => {
+                        redirects += 1;
+                        action = monitor.receive(packet);
+                    }
+                    Err(_) => return false,
+                },
+                Action::Stop => return false,
+            }
+        }
+    }
+
+    fn node_index(&self, address: i64) -> Option<usize> {
+        if address >= 0 && (address as usize) < self.nodes.len() {
+            Some(address as usize)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Monitor, Nat, Network, Packet, Scheduler, MAX_REDIRECTS};
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::Memory;
+
+    /// Machine 0 starts by sending (0, 7) to machine 1. Every machine forwards the packets it
+    /// receives to the next address, and the last machine forwards them to 255.
+    fn ring(size: usize) -> Memory {
+        assemble(&format!(
+            "
+                IN -> [address]
+                JNZ [address], loop
+                OUT 1
+                OUT 0
+                OUT 7
+        loop:   IN -> [x]
+                EQ [x], -1 -> [flag]
+                JNZ [flag], loop
+                IN -> [y]
+                ADD [address], 1 -> [next]
+                EQ [next], {} -> [flag]
+                JZ [flag], send
+                ADD 255, 0 -> [next]
+        send:   OUT [next]
+                OUT [x]
+                OUT [y]
+                JNZ 1, loop
+        address: DATA 0
+        next:    DATA 0
+        flag:    DATA 0
+        x:       DATA 0
+        y:       DATA 0
+            ",
+            size
+        ))
+        .expect("Could not assemble")
+    }
+
+    #[test]
+    fn test_nat() {
+        for scheduler in [Scheduler::SingleThreaded, Scheduler::Threads(2)].iter() {
+            let mut network = Network::new(&ring(5), 5);
+            network.set_scheduler(*scheduler);
+            let mut nat = Nat::new(255);
+            network.run(&mut nat).expect("Network failed");
+            assert_eq!(
+                Some(Packet {
+                    destination: 255,
+                    x: 0,
+                    y: 7
+                }),
+                nat.first_packet
+            );
+            assert_eq!(Some(7), nat.repeated_y);
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        packets: Vec<Packet>,
+        idle_rounds: usize,
+    }
+
+    impl Monitor for Recorder {
+        fn receive(&mut self, packet: Packet) -> Action {
+            self.packets.push(packet);
+            Action::Continue
+        }
+
+        fn idle(&mut self) -> Action {
+            self.idle_rounds += 1;
+            if self.idle_rounds == 1 {
+                Action::Send(Packet {
+                    destination: 1,
+                    x: 3,
+                    y: 4,
+                })
+            } else {
+                Action::Continue
+            }
+        }
+    }
+
+    #[test]
+    fn test_monitor() {
+        let mut network = Network::new(&ring(3), 3);
+        let mut recorder = Recorder::default();
+        network.run(&mut recorder).expect("Network failed");
+        assert_eq!(
+            vec![
+                Packet {
+                    destination: 255,
+                    x: 0,
+                    y: 7
+                },
+                Packet {
+                    destination: 255,
+                    x: 3,
+                    y: 4
+                }
+            ],
+            recorder.packets
+        );
+        assert_eq!(2, recorder.idle_rounds);
+        assert_eq!(
+            Err(Packet {
+                destination: 3,
+                x: 0,
+                y: 0
+            }),
+            network.send(Packet {
+                destination: 3,
+                x: 0,
+                y: 0
+            })
+        );
+    }
+
+    /// Sends every packet it receives on to another address that no machine has.
+    struct Redirector {
+        received: usize,
+    }
+
+    impl Monitor for Redirector {
+        fn receive(&mut self, packet: Packet) -> Action {
+            self.received += 1;
+            Action::Send(Packet {
+                destination: packet.destination + 1,
+                ..packet
+            })
+        }
+
+        fn idle(&mut self) -> Action {
+            Action::Continue
+        }
+    }
+
+    #[test]
+    fn test_redirect_limit() {
+        let mut network = Network::new(&ring(3), 3);
+        let mut redirector = Redirector { received: 0 };
+        network.run(&mut redirector).expect("Network failed");
+        assert_eq!(MAX_REDIRECTS + 1, redirector.received);
+    }
+}
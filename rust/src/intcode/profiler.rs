@@ -0,0 +1,250 @@
+use super::disassembler::decode;
+use super::trace::{TraceEvent, Tracer};
+use super::{InstructionSet, Memory};
+use std::collections::{BTreeMap, HashMap};
+
+/// Counts executed instructions. Install it on a `Computer` with `set_tracer`, wrapped in an
+/// `Arc<Mutex<_>>` to read the results afterwards.
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    cycles: u64,
+    addresses: HashMap<usize, u64>,
+    opcodes: BTreeMap<i64, u64>,
+    jump_targets: HashMap<usize, u64>,
+    /// Taken jumps to an earlier address, keyed by (source, target).
+    back_edges: HashMap<(usize, usize), u64>,
+}
+
+/// A range of addresses that a backward jump executed repeatedly.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64,
+    /// The number of instructions executed inside the range.
+    pub instructions: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total number of executed instructions.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn address_count(&self, address: usize) -> u64 {
+        self.addresses.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn opcode_histogram(&self) -> &BTreeMap<i64, u64> {
+        &self.opcodes
+    }
+
+    /// How often each address was the target of a taken jump.
+    pub fn jump_targets(&self) -> &HashMap<usize, u64> {
+        &self.jump_targets
+    }
+
+    /// The addresses that executed most often, most frequent first.
+    pub fn hot_addresses(&self, limit: usize) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<(usize, u64)> =
+            self.addresses.iter().map(|(a, c)| (*a, *c)).collect();
+        addresses.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0)));
+        addresses.truncate(limit);
+        addresses
+    }
+
+    /// Loops formed by backward jumps, ordered by the number of instructions executed inside
+    /// them.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .back_edges
+            .iter()
+            .map(|(&(end, start), &iterations)| HotLoop {
+                start,
+                end,
+                iterations,
+                instructions: self
+                    .addresses
+                    .iter()
+                    .filter(|(address, _)| (start..=end).contains(*address))
+                    .map(|(_, count)| count)
+                    .sum(),
+            })
+            .collect();
+        loops.sort_by(|x, y| {
+            y.instructions
+                .cmp(&x.instructions)
+                .then(x.start.cmp(&y.start))
+        });
+        loops
+    }
+
+    /// Renders a summary of the profile, using `instruction_set` and `memory` to disassemble hot
+    /// addresses.
+    pub fn report(
+        &self,
+        instruction_set: &InstructionSet,
+        memory: &Memory,
+        limit: usize,
+    ) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.cycles.max(1) as f64;
+        let mut lines = vec![format!("Instructions executed: {}", self.cycles)];
+
+        lines.push(String::new());
+        lines.push("Hot loops:".to_string());
+        for hot_loop in self.hot_loops().iter().take(limit) {
+            lines.push(format!(
+                "  {:04}-{:04} {:>10} iterations {:>12} instructions {:>5.1}%",
+                hot_loop.start,
+                hot_loop.end,
+                hot_loop.iterations,
+                hot_loop.instructions,
+                percent(hot_loop.instructions)
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("Hot addresses:".to_string());
+        for (address, count) in self.hot_addresses(limit) {
+            lines.push(format!(
+                "  {:04} {:>12} {:>5.1}%  {}",
+                address,
+                count,
+                percent(count),
+                decode(instruction_set, memory, address)
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("Opcodes:".to_string());
+        for (opcode, count) in self.opcodes.iter() {
+            let mnemonic = instruction_set
+                .get(*opcode)
+                .map_or("?", |opcode| opcode.mnemonic.as_str());
+            lines.push(format!(
+                "  {:<4} {:>12} {:>5.1}%",
+                mnemonic,
+                count,
+                percent(*count)
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("Jump targets:".to_string());
+        let mut targets: Vec<(&usize, &u64)> = self.jump_targets.iter().collect();
+        targets.sort_by(|x, y| y.1.cmp(x.1).then(x.0.cmp(y.0)));
+        for (target, count) in targets.into_iter().take(limit) {
+            lines.push(format!("  {:04} {:>12}", target, count));
+        }
+
+        lines.join("\n") + "\n"
+    }
+}
+
+impl Tracer for Profiler {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.cycles += 1;
+        *self.addresses.entry(event.pointer).or_insert(0) += 1;
+        *self.opcodes.entry(event.opcode).or_insert(0) += 1;
+
+        if let Some(target) = event.jump {
+            *self.jump_targets.entry(target).or_insert(0) += 1;
+            if target <= event.pointer {
+                *self.back_edges.entry((event.pointer, target)).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HotLoop, Profiler};
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::{Computer, Effect, Opcode};
+    use std::sync::{Arc, Mutex};
+
+    fn profile() -> (Computer, Profiler) {
+        let memory = assemble(
+            "
+            loop:
+                ADD [counter], 1 -> [counter]
+                LT [counter], 10 -> [flag]
+                JNZ [flag], loop
+                OUT [counter]
+                HLT
+            counter: DATA 0
+            flag: DATA 0
+            ",
+        )
+        .expect("Could not assemble");
+        let profiler = Arc::new(Mutex::new(Profiler::new()));
+        let mut computer = Computer::new(memory);
+        computer.set_tracer(Arc::clone(&profiler));
+        assert_eq!(Ok(Some(10)), computer.execute(None));
+        let profiler = profiler.lock().unwrap().clone();
+        (computer, profiler)
+    }
+
+    #[test]
+    fn test_counts() {
+        let (_, profiler) = profile();
+        assert_eq!(32, profiler.cycles());
+        assert_eq!(10, profiler.address_count(0));
+        assert_eq!(1, profiler.address_count(11));
+        assert_eq!(0, profiler.address_count(1));
+        assert_eq!(
+            vec![(1, 10), (4, 1), (5, 10), (7, 10), (99, 1)],
+            profiler
+                .opcode_histogram()
+                .iter()
+                .map(|(opcode, count)| (*opcode, *count))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&9), profiler.jump_targets().get(&0));
+        assert_eq!(vec![(0, 10), (4, 10)], profiler.hot_addresses(2));
+    }
+
+    #[test]
+    fn test_registered_jump() {
+        let profiler = Arc::new(Mutex::new(Profiler::new()));
+        let mut computer = Computer::builder(vec![120, 3, 0, 99])
+            .opcode(Opcode::new(20, "JMP", 1, false, |operands| {
+                Ok(Effect::Jump(operands.read(0)?))
+            }))
+            .build();
+        computer.set_tracer(Arc::clone(&profiler));
+        assert_eq!(Ok(None), computer.execute(None));
+        assert_eq!(Some(&1), profiler.lock().unwrap().jump_targets().get(&3));
+    }
+
+    #[test]
+    fn test_hot_loops() {
+        let (_, profiler) = profile();
+        assert_eq!(
+            vec![HotLoop {
+                start: 0,
+                end: 8,
+                iterations: 9,
+                instructions: 30,
+            }],
+            profiler.hot_loops()
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let (computer, profiler) = profile();
+        let report = profiler.report(computer.instruction_set(), &computer.memory, 1);
+        assert!(report.starts_with("Instructions executed: 32\n"));
+        assert!(
+            report.contains("  0000-0008          9 iterations           30 instructions  93.8%")
+        );
+        assert!(report.contains("  0000           10  31.2%  ADD [14], 1 -> [14]"));
+        assert!(report.contains("  JNZ            10  31.2%"));
+        assert!(report.contains("  0000            9"));
+    }
+}
@@ -0,0 +1,410 @@
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::{error, fmt, io};
+
+pub type Point = (i64, i64);
+
+/// Programs draw to this coordinate to set the score instead of a tile.
+pub const SCORE: Point = (-1, 0);
+
+/// The largest number of tiles in a rendered screen or pixels in an image.
+const MAX_AREA: usize = 1 << 24;
+
+/// The drawn tiles span too large an area to render, because they are far apart or the scale
+/// is too large.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BoundsError {
+    pub bounds: (Point, Point),
+    pub scale: usize,
+}
+
+impl fmt::Display for BoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ((left, top), (right, bottom)) = self.bounds;
+        write!(
+            f,
+            "Cannot render tiles from ({}, {}) to ({}, {}) at scale {}: more than {} pixels",
+            left, top, right, bottom, self.scale, MAX_AREA
+        )
+    }
+}
+
+impl error::Error for BoundsError {}
+
+impl From<BoundsError> for io::Error {
+    fn from(error: BoundsError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// The width and height of `bounds` with `scale` pixels per tile.
+fn size(bounds: (Point, Point), scale: usize) -> Result<(usize, usize), BoundsError> {
+    let ((left, top), (right, bottom)) = bounds;
+    let span = |low: i64, high: i64| {
+        let tiles = high.checked_sub(low)?.checked_add(1)?;
+        usize::try_from(tiles).ok()?.checked_mul(scale)
+    };
+    match (span(left, right), span(top, bottom)) {
+        (Some(width), Some(height)) if matches!(width.checked_mul(height), Some(area) if area <= MAX_AREA) => {
+            Ok((width, height))
+        }
+        _ => Err(BoundsError { bounds, scale }),
+    }
+}
+
+/// How a tile looks on the terminal and in images.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Tile {
+    pub symbol: char,
+    pub color: [u8; 3],
+}
+
+/// Drawn for tiles that are missing from a palette.
+const UNKNOWN: Tile = Tile {
+    symbol: '?',
+    color: [255, 0, 255],
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    tiles: BTreeMap<i64, Tile>,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, tile: i64, symbol: char, color: [u8; 3]) -> Self {
+        self.tiles.insert(tile, Tile { symbol, color });
+        self
+    }
+
+    /// Black and white hull panels.
+    pub fn panels() -> Self {
+        Self::new()
+            .with(0, '.', [0, 0, 0])
+            .with(1, '#', [255, 255, 255])
+    }
+
+    /// Empty space, walls, blocks, the paddle and the ball of the arcade cabinet.
+    pub fn arcade() -> Self {
+        Self::new()
+            .with(0, ' ', [0, 0, 0])
+            .with(1, '#', [128, 128, 128])
+            .with(2, '=', [64, 96, 224])
+            .with(3, '-', [255, 255, 255])
+            .with(4, 'o', [255, 208, 0])
+    }
+
+    pub fn tile(&self, tile: i64) -> Tile {
+        self.tiles.get(&tile).copied().unwrap_or(UNKNOWN)
+    }
+}
+
+/// A sparse grid of tiles, drawn from `x, y, tile` output triples. Undrawn tiles are 0.
+#[derive(Clone, Debug, Default)]
+pub struct Screen {
+    tiles: HashMap<Point, i64>,
+    score: Option<i64>,
+    /// Output values that do not form a whole triple yet.
+    partial: Vec<i64>,
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draws output triples. A trailing partial triple is kept until the rest of it arrives.
+    pub fn draw<I: IntoIterator<Item = i64>>(&mut self, values: I) {
+        self.partial.extend(values);
+        let whole = self.partial.len() / 3 * 3;
+        let values: Vec<i64> = self.partial.drain(..whole).collect();
+        for triple in values.chunks(3) {
+            self.paint((triple[0], triple[1]), triple[2]);
+        }
+    }
+
+    pub fn paint(&mut self, point: Point, tile: i64) {
+        if point == SCORE {
+            self.score = Some(tile);
+        } else {
+            self.tiles.insert(point, tile);
+        }
+    }
+
+    pub fn get(&self, point: Point) -> i64 {
+        self.tiles.get(&point).copied().unwrap_or(0)
+    }
+
+    pub fn score(&self) -> Option<i64> {
+        self.score
+    }
+
+    /// The points of every drawn tile.
+    pub fn points(&self) -> impl Iterator<Item = (&Point, &i64)> {
+        self.tiles.iter()
+    }
+
+    /// The top left and bottom right corners of the drawn tiles.
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        bounds(self.tiles.keys())
+    }
+
+    /// Renders the screen as text for the terminal, followed by the score if there is one.
+    pub fn render(&self, palette: &Palette) -> Result<String, BoundsError> {
+        let mut text = String::new();
+        if let Some(bounds) = self.bounds() {
+            size(bounds, 1)?;
+            let ((left, top), (right, bottom)) = bounds;
+            for y in top..=bottom {
+                text.extend((left..=right).map(|x| palette.tile(self.get((x, y))).symbol));
+                text.push('\n');
+            }
+        }
+        if let Some(score) = self.score {
+            text.push_str(&format!("Score: {}\n", score));
+        }
+
+        Ok(text)
+    }
+
+    /// Draws the screen as an image with `scale` pixels per tile.
+    pub fn image(&self, palette: &Palette, scale: usize) -> Result<Image, BoundsError> {
+        let bounds = self.bounds().unwrap_or(((0, 0), (0, 0)));
+        self.image_within(bounds, palette, scale)
+    }
+
+    fn image_within(
+        &self,
+        bounds: (Point, Point),
+        palette: &Palette,
+        scale: usize,
+    ) -> Result<Image, BoundsError> {
+        let (width, height) = size(bounds, scale)?;
+        let (left, top) = bounds.0;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let point = (left + (x / scale) as i64, top + (y / scale) as i64);
+                pixels.push(palette.tile(self.get(point)).color);
+            }
+        }
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+fn bounds<'a, I: Iterator<Item = &'a Point>>(points: I) -> Option<(Point, Point)> {
+    points.fold(None, |bounds, &(x, y)| match bounds {
+        None => Some(((x, y), (x, y))),
+        Some(((left, top), (right, bottom))) => {
+            Some(((left.min(x), top.min(y)), (right.max(x), bottom.max(y))))
+        }
+    })
+}
+
+/// An RGB image, stored row by row.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    /// Writes the image as a binary PPM.
+    pub fn write_ppm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            writer.write_all(pixel)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the image as an 8-bit RGB PNG.
+    pub fn write_png<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = vec![];
+        header.extend(&(self.width as u32).to_be_bytes());
+        header.extend(&(self.height as u32).to_be_bytes());
+        // Bit depth 8, truecolor, default compression, filter and no interlacing.
+        header.extend(&[8, 2, 0, 0, 0]);
+        write_chunk(&mut writer, b"IHDR", &header)?;
+
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        for row in self.pixels.chunks(self.width.max(1)) {
+            // Each row starts with its filter type, which is always "none" here.
+            encoder.write_all(&[0])?;
+            for pixel in row {
+                encoder.write_all(pixel)?;
+            }
+        }
+        write_chunk(&mut writer, b"IDAT", &encoder.finish()?)?;
+        write_chunk(&mut writer, b"IEND", &[])
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.sum().to_be_bytes())
+}
+
+/// Screens captured over the course of a run.
+#[derive(Clone, Debug, Default)]
+pub struct Animation {
+    pub frames: Vec<Screen>,
+}
+
+impl Animation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn capture(&mut self, screen: &Screen) {
+        self.frames.push(screen.clone());
+    }
+
+    /// Draws every frame with the same bounds, so that they line up.
+    pub fn images(&self, palette: &Palette, scale: usize) -> Result<Vec<Image>, BoundsError> {
+        let bounds = bounds(self.frames.iter().flat_map(|frame| frame.tiles.keys()))
+            .unwrap_or(((0, 0), (0, 0)));
+        self.frames
+            .iter()
+            .map(|frame| frame.image_within(bounds, palette, scale))
+            .collect()
+    }
+
+    /// Writes each frame to `directory` as `frame0000.png`, `frame0001.png` and so on.
+    pub fn write_png_frames(
+        &self,
+        directory: &Path,
+        palette: &Palette,
+        scale: usize,
+    ) -> io::Result<()> {
+        for (index, image) in self.images(palette, scale)?.iter().enumerate() {
+            let file = File::create(directory.join(format!("frame{:04}.png", index)))?;
+            let mut writer = BufWriter::new(file);
+            image.write_png(&mut writer)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Animation, BoundsError, Palette, Screen};
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn screen() -> Screen {
+        let mut screen = Screen::new();
+        screen.draw(vec![0, 0, 1, 1, 0, 1, 2, 0, 1, 0, 1, 1, 1, 1, 4, 2, 1]);
+        screen.draw(vec![1, -1, 0, 12345]);
+        screen
+    }
+
+    #[test]
+    fn test_draw() {
+        let screen = screen();
+        assert_eq!(4, screen.get((1, 1)));
+        assert_eq!(1, screen.get((2, 1)));
+        assert_eq!(0, screen.get((9, 9)));
+        assert_eq!(Some(12345), screen.score());
+        assert_eq!(Some(((0, 0), (2, 1))), screen.bounds());
+        assert_eq!(
+            Ok("###\n#o#\nScore: 12345\n".to_string()),
+            screen.render(&Palette::arcade())
+        );
+
+        let mut unknown = Screen::new();
+        unknown.paint((0, 0), 7);
+        assert_eq!(Ok("?\n".to_string()), unknown.render(&Palette::panels()));
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut screen = Screen::new();
+        screen.draw(vec![0, 0, 1, 1, 0, 0]);
+        let mut ppm = vec![];
+        screen
+            .image(&Palette::panels(), 1)
+            .expect("Screen is too large")
+            .write_ppm(&mut ppm)
+            .expect("Could not write PPM");
+        assert_eq!(b"P6\n2 1\n255\n\xff\xff\xff\x00\x00\x00".to_vec(), ppm);
+    }
+
+    #[test]
+    fn test_png() {
+        let image = screen()
+            .image(&Palette::arcade(), 2)
+            .expect("Screen is too large");
+        assert_eq!((6, 4), (image.width, image.height));
+        let mut png = vec![];
+        image.write_png(&mut png).expect("Could not write PNG");
+        assert_eq!(b"\x89PNG\r\n\x1a\n", &png[..8]);
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert_eq!([0, 0, 0, 6, 0, 0, 0, 4], png[16..24]);
+
+        let length = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+        assert_eq!(b"IDAT", &png[37..41]);
+        let mut rows = vec![];
+        ZlibDecoder::new(&png[41..41 + length])
+            .read_to_end(&mut rows)
+            .expect("Could not decompress");
+        assert_eq!(4 * (1 + 6 * 3), rows.len());
+        assert_eq!([0, 128, 128, 128], rows[..4]);
+    }
+
+    #[test]
+    fn test_animation() {
+        let mut screen = Screen::new();
+        let mut animation = Animation::new();
+        screen.draw(vec![0, 0, 4]);
+        animation.capture(&screen);
+        screen.draw(vec![0, 0, 0, 1, 0, 4]);
+        animation.capture(&screen);
+        let images = animation
+            .images(&Palette::arcade(), 1)
+            .expect("Screen is too large");
+        assert_eq!(2, images.len());
+        assert_eq!(images[0].pixels, vec![[255, 208, 0], [0, 0, 0]]);
+        assert_eq!(images[1].pixels, vec![[0, 0, 0], [255, 208, 0]]);
+    }
+
+    #[test]
+    fn test_bounds_too_large() {
+        let mut screen = Screen::new();
+        screen.draw(vec![i64::MIN, 0, 1, i64::MAX, 0, 1]);
+        let error = BoundsError {
+            bounds: ((i64::MIN, 0), (i64::MAX, 0)),
+            scale: 1,
+        };
+        assert_eq!(Err(error), screen.render(&Palette::panels()));
+        assert_eq!(Err(error), screen.image(&Palette::panels(), 1));
+
+        let mut screen = Screen::new();
+        screen.draw(vec![0, 0, 1, 9, 9, 1]);
+        assert!(screen.image(&Palette::panels(), 1).is_ok());
+        assert!(screen.image(&Palette::panels(), usize::MAX).is_err());
+        assert!(screen.image(&Palette::panels(), 1 << 12).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use super::{Computer, Memory};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"ICS1";
+
+/// The complete state of a `Computer`, apart from its input source, output sink and tracer.
+/// Snapshots share memory pages with the computer they were taken from until either one
+/// writes to them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub memory: Memory,
+    pub pointer: usize,
+    pub relative_base: i64,
+    /// Input values queued with `push_input` that have not been read yet.
+    pub inputs: VecDeque<i64>,
+    /// Output values that have not been taken from the output queue yet.
+    pub outputs: VecDeque<i64>,
+}
+
+impl Snapshot {
+    /// Serialises the snapshot in a little-endian binary format.
+    pub fn write_to<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u64(&mut writer, self.pointer as u64)?;
+        write_i64(&mut writer, self.relative_base)?;
+        for queue in [&self.inputs, &self.outputs].iter() {
+            write_u64(&mut writer, queue.len() as u64)?;
+            for value in queue.iter() {
+                write_i64(&mut writer, *value)?;
+            }
+        }
+
+        let dense = self.memory.to_vec();
+        write_u64(&mut writer, dense.len() as u64)?;
+        for value in dense {
+            write_i64(&mut writer, value)?;
+        }
+        let sparse = self.memory.sparse_words();
+        write_u64(&mut writer, sparse.len() as u64)?;
+        for (address, value) in sparse {
+            write_u64(&mut writer, address as u64)?;
+            write_i64(&mut writer, value)?;
+        }
+
+        writer.flush()
+    }
+
+    pub fn read_from<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not an intcode snapshot",
+            ));
+        }
+        let pointer = read_u64(&mut reader)? as usize;
+        let relative_base = read_i64(&mut reader)?;
+        let mut queues = vec![];
+        for _ in 0..2 {
+            let count = read_u64(&mut reader)?;
+            let queue = (0..count)
+                .map(|_| read_i64(&mut reader))
+                .collect::<io::Result<VecDeque<i64>>>()?;
+            queues.push(queue);
+        }
+        let outputs = queues.pop().unwrap_or_default();
+        let inputs = queues.pop().unwrap_or_default();
+
+        let mut memory = Memory::new();
+        for address in 0..read_u64(&mut reader)? as usize {
+            memory.set(address, read_i64(&mut reader)?);
+        }
+        for _ in 0..read_u64(&mut reader)? {
+            let address = read_u64(&mut reader)? as usize;
+            memory.set(address, read_i64(&mut reader)?);
+        }
+
+        Ok(Self {
+            memory,
+            pointer,
+            relative_base,
+            inputs,
+            outputs,
+        })
+    }
+}
+
+fn write_u64<W: io::Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_i64<W: io::Write>(writer: &mut W, value: i64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u64<R: io::Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_i64<R: io::Read>(reader: &mut R) -> io::Result<i64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(i64::from_le_bytes(bytes))
+}
+
+impl Computer {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            pointer: self.pointer,
+            relative_base: self.relative_base,
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+        }
+    }
+
+    /// Returns to the state in `snapshot`. The input source, output sink and tracer are kept.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory = snapshot.memory.clone();
+        self.pointer = snapshot.pointer;
+        self.relative_base = snapshot.relative_base;
+        self.inputs = snapshot.inputs.clone();
+        self.outputs = snapshot.outputs.clone();
+        self.last_write = None;
+        if self.loop_detector.is_some() {
+            self.set_loop_detection(true);
+        }
+    }
+
+    /// Creates a computer in the state in `snapshot`, with the core instruction set and no
+    /// limits.
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut computer = Self::new(Memory::new());
+        computer.restore(snapshot);
+        computer
+    }
+
+    /// Creates an independent copy of the machine state that shares memory pages with this one
+    /// until either writes to them. The copy keeps the instruction set, the decode cache, the
+    /// instruction limit and the count towards it, the deadline and loop detection, but has no
+    /// input source, output sink or tracer.
+    pub fn fork(&self) -> Self {
+        let mut computer = Self::from_snapshot(&self.snapshot());
+        computer.instruction_set = Arc::clone(&self.instruction_set);
+        computer.decode_cache = self.decode_cache.clone();
+        computer.instruction_limit = self.instruction_limit;
+        computer.deadline = self.deadline;
+        computer.instructions = self.instructions;
+        computer.loop_detector = self.loop_detector.clone();
+        computer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use crate::intcode::assembler::assemble;
+    use crate::intcode::{Computer, IntcodeError, State};
+
+    fn maze() -> Computer {
+        // Reads a direction, then reports 1 for direction 3 and 0 for anything else.
+        Computer::new(
+            assemble(
+                "
+                IN -> [direction]
+                EQ [direction], 3 -> [found]
+                OUT [found]
+                HLT
+            direction: DATA 0
+            found: DATA 0
+                ",
+            )
+            .expect("Could not assemble"),
+        )
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut computer = maze();
+        assert_eq!(Ok(State::NeedsInput), computer.resume());
+        let results: Vec<i64> = (1..=4)
+            .map(|direction| {
+                let mut branch = computer.fork();
+                branch.push_input(direction);
+                match branch.resume() {
+                    Ok(State::Output(value)) => value,
+                    state => panic!("Unexpected state {:?}", state),
+                }
+            })
+            .collect();
+        assert_eq!(vec![0, 0, 1, 0], results);
+        assert_eq!(0, computer.pointer());
+        assert_eq!(0, computer.memory.get(12));
+    }
+
+    #[test]
+    fn test_fork_keeps_limits() {
+        let mut computer = Computer::new(vec![1105, 1, 0]);
+        computer.set_instruction_limit(Some(10));
+        for _ in 0..4 {
+            assert_eq!(Ok(State::Running), computer.step());
+        }
+        let mut branch = computer.fork();
+        assert_eq!(
+            Err(IntcodeError::InstructionLimitExceeded { limit: 10 }),
+            branch.resume()
+        );
+        assert_eq!(10, branch.instructions_executed());
+
+        computer.set_instruction_limit(None);
+        computer.set_loop_detection(true);
+        assert_eq!(
+            Err(IntcodeError::InfiniteLoop { pointer: 0 }),
+            computer.fork().resume()
+        );
+
+        assert!(computer.fork().decode_cache.is_some());
+        computer.set_decode_cache(false);
+        assert!(computer.fork().decode_cache.is_none());
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut computer = maze();
+        computer.resume().expect("Program failed");
+        let snapshot = computer.snapshot();
+        computer.push_input(3);
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![1], computer.take_outputs());
+
+        computer.restore(&snapshot);
+        assert_eq!(snapshot, computer.snapshot());
+        computer.push_input(2);
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(vec![0], computer.take_outputs());
+    }
+
+    #[test]
+    fn test_serialise() {
+        let mut computer = Computer::new(vec![109, 2_000_000, 21101, 4, 5, 7, 104, 6, 3, 0, 99]);
+        computer.push_input(8);
+        computer.push_input(9);
+        assert_eq!(Ok(State::Output(6)), computer.resume());
+        computer.outputs.push_back(6);
+        let snapshot = computer.snapshot();
+
+        let mut bytes = vec![];
+        snapshot.write_to(&mut bytes).expect("Could not write");
+        let restored = Snapshot::read_from(bytes.as_slice()).expect("Could not read");
+        assert_eq!(snapshot, restored);
+        assert_eq!(9, restored.memory.get(2_000_007));
+
+        let mut resumed = Computer::from_snapshot(&restored);
+        assert_eq!(Ok(State::Halted), resumed.run());
+        assert_eq!(8, resumed.memory.get(0));
+        assert_eq!(vec![6], resumed.take_outputs());
+        assert!(Snapshot::read_from(&b"ICS0"[..]).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+const BINARY_MAGIC: &[u8; 4] = b"ICT1";
+
+/// One executed instruction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceEvent {
+    pub pointer: usize,
+    pub opcode: i64,
+    /// The mode code of each parameter.
+    pub modes: Vec<i64>,
+    /// The value of each parameter that is read, followed by the address of the parameter that
+    /// is written to, if any.
+    pub operands: Vec<i64>,
+    /// The address and value written to memory.
+    pub write: Option<(usize, i64)>,
+    /// The address the instruction jumped to, if it jumped.
+    pub jump: Option<usize>,
+}
+
+impl TraceEvent {
+    pub fn to_json(&self) -> String {
+        let join = |values: &[i64]| {
+            values
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        let write = match self.write {
+            Some((address, value)) => format!("[{},{}]", address, value),
+            None => "null".to_string(),
+        };
+        let jump = match self.jump {
+            Some(target) => target.to_string(),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"pointer\":{},\"opcode\":{},\"modes\":[{}],\"operands\":[{}],\"write\":{},\"jump\":{}}}",
+            self.pointer,
+            self.opcode,
+            join(&self.modes),
+            join(&self.operands),
+            write,
+            jump
+        )
+    }
+
+    fn write_binary<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&(self.pointer as u64).to_le_bytes())?;
+        writer.write_all(&[self.opcode as u8, self.modes.len() as u8])?;
+        for (mode, operand) in self.modes.iter().zip(self.operands.iter()) {
+            writer.write_all(&[*mode as u8])?;
+            writer.write_all(&operand.to_le_bytes())?;
+        }
+        match self.write {
+            Some((address, value)) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&(address as u64).to_le_bytes())?;
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+        match self.jump {
+            Some(target) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&(target as u64).to_le_bytes())
+            }
+            None => writer.write_all(&[0]),
+        }
+    }
+
+    /// Returns `None` at the end of the input.
+    fn read_binary<R: io::Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut pointer = [0; 8];
+        match reader.read_exact(&mut pointer) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let mut header = [0; 2];
+        reader.read_exact(&mut header)?;
+        let mut modes = vec![];
+        let mut operands = vec![];
+        for _ in 0..header[1] {
+            let mut mode = [0];
+            reader.read_exact(&mut mode)?;
+            modes.push(i64::from(mode[0]));
+            operands.push(read_i64(reader)?);
+        }
+        let mut has_write = [0];
+        reader.read_exact(&mut has_write)?;
+        let write = if has_write[0] == 1 {
+            Some((read_i64(reader)? as usize, read_i64(reader)?))
+        } else {
+            None
+        };
+        let mut has_jump = [0];
+        reader.read_exact(&mut has_jump)?;
+        let jump = if has_jump[0] == 1 {
+            Some(read_i64(reader)? as usize)
+        } else {
+            None
+        };
+
+        Ok(Some(Self {
+            pointer: u64::from_le_bytes(pointer) as usize,
+            opcode: i64::from(header[0]),
+            modes,
+            operands,
+            write,
+            jump,
+        }))
+    }
+}
+
+fn read_i64<R: io::Read>(reader: &mut R) -> io::Result<i64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(i64::from_le_bytes(bytes))
+}
+
+/// Receives an event for every instruction a `Computer` executes.
+pub trait Tracer {
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+impl Tracer for Vec<TraceEvent> {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.push(event.clone());
+    }
+}
+
+/// Lets the caller keep a handle to a tracer that a `Computer` owns.
+impl<T: Tracer> Tracer for Arc<Mutex<T>> {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.lock().expect("Tracer lock is poisoned").trace(event);
+    }
+}
+
+/// Writes one JSON object per event and line. Write errors are kept until `finish`.
+pub struct JsonLinesTracer<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flushes the trace and returns the writer, or the first error that occurred.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: io::Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", event.to_json()) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+/// Writes events in a compact little-endian binary form that `read_binary_trace` reads back.
+/// Write errors are kept until `finish`.
+pub struct BinaryTracer<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> BinaryTracer<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(BINARY_MAGIC)?;
+        Ok(Self {
+            writer,
+            error: None,
+        })
+    }
+
+    /// Flushes the trace and returns the writer, or the first error that occurred.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: io::Write> Tracer for BinaryTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            if let Err(error) = event.write_binary(&mut self.writer) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+pub fn read_binary_trace<R: io::Read>(mut reader: R) -> io::Result<Vec<TraceEvent>> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != BINARY_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a binary intcode trace",
+        ));
+    }
+    let mut events = vec![];
+    while let Some(event) = TraceEvent::read_binary(&mut reader)? {
+        events.push(event);
+    }
+
+    Ok(events)
+}
+
+/// Returns the index of the first event where two traces differ, or `None` if they match.
+pub fn first_difference(expected: &[TraceEvent], actual: &[TraceEvent]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(actual.iter())
+        .position(|(expected, actual)| expected != actual)
+        .or_else(|| {
+            if expected.len() == actual.len() {
+                None
+            } else {
+                Some(expected.len().min(actual.len()))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{first_difference, read_binary_trace, BinaryTracer, JsonLinesTracer, TraceEvent};
+    use crate::intcode::Computer;
+    use std::sync::{Arc, Mutex};
+
+    fn events() -> Vec<TraceEvent> {
+        let trace = Arc::new(Mutex::new(vec![]));
+        let mut computer = Computer::new(vec![3, 9, 1002, 9, 3, 9, 4, 9, 99, 0]);
+        computer.set_tracer(Arc::clone(&trace));
+        assert_eq!(Ok(Some(21)), computer.execute(Some(7)));
+        let events = trace.lock().unwrap().clone();
+        events
+    }
+
+    #[test]
+    fn test_events() {
+        assert_eq!(
+            vec![
+                TraceEvent {
+                    pointer: 0,
+                    opcode: 3,
+                    modes: vec![0],
+                    operands: vec![9],
+                    write: Some((9, 7)),
+                    jump: None,
+                },
+                TraceEvent {
+                    pointer: 2,
+                    opcode: 2,
+                    modes: vec![0, 1, 0],
+                    operands: vec![7, 3, 9],
+                    write: Some((9, 21)),
+                    jump: None,
+                },
+                TraceEvent {
+                    pointer: 6,
+                    opcode: 4,
+                    modes: vec![0],
+                    operands: vec![21],
+                    write: None,
+                    jump: None,
+                },
+                TraceEvent {
+                    pointer: 8,
+                    opcode: 99,
+                    modes: vec![],
+                    operands: vec![],
+                    write: None,
+                    jump: None,
+                },
+            ],
+            events()
+        );
+    }
+
+    #[test]
+    fn test_jumps() {
+        let trace = Arc::new(Mutex::new(vec![]));
+        let mut computer = Computer::new(vec![1105, 1, 4, 99, 1105, 0, 0, 1106, 0, 3]);
+        computer.set_tracer(Arc::clone(&trace));
+        assert_eq!(Ok(None), computer.execute(None));
+        let jumps: Vec<Option<usize>> = trace
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| event.jump)
+            .collect();
+        assert_eq!(vec![Some(4), None, Some(3), None], jumps);
+    }
+
+    #[test]
+    fn test_needs_input_is_not_traced() {
+        let trace = Arc::new(Mutex::new(vec![]));
+        let mut computer = Computer::new(vec![3, 3, 99, 0]);
+        computer.set_tracer(Arc::clone(&trace));
+        computer.resume().expect("Program failed");
+        assert!(trace.lock().unwrap().is_empty());
+        computer.push_input(1);
+        computer.resume().expect("Program failed");
+        assert_eq!(2, trace.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut computer = Computer::new(vec![1101, 2, 3, 5, 99, 0]);
+        computer.set_tracer(JsonLinesTracer::new(vec![]));
+        computer.execute(None).expect("Program failed");
+        assert!(computer.take_tracer().is_some());
+        assert!(computer.take_tracer().is_none());
+
+        let mut tracer = JsonLinesTracer::new(vec![]);
+        for event in events().iter().take(2) {
+            super::Tracer::trace(&mut tracer, event);
+        }
+        assert_eq!(
+            "{\"pointer\":0,\"opcode\":3,\"modes\":[0],\"operands\":[9],\"write\":[9,7],\"jump\":null}
+{\"pointer\":2,\"opcode\":2,\"modes\":[0,1,0],\"operands\":[7,3,9],\"write\":[9,21],\"jump\":null}
+",
+            String::from_utf8(tracer.finish().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let events = events();
+        let mut tracer = BinaryTracer::new(vec![]).unwrap();
+        for event in events.iter() {
+            super::Tracer::trace(&mut tracer, event);
+        }
+        let bytes = tracer.finish().unwrap();
+        assert_eq!(events, read_binary_trace(bytes.as_slice()).unwrap());
+        assert!(read_binary_trace(&b"JUNK"[..]).is_err());
+    }
+
+    #[test]
+    fn test_first_difference() {
+        let events = events();
+        assert_eq!(None, first_difference(&events, &events));
+        assert_eq!(Some(3), first_difference(&events, &events[..3]));
+        let mut changed = events.clone();
+        changed[1].write = Some((9, 20));
+        assert_eq!(Some(1), first_difference(&events, &changed));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod day1;
+pub mod day3;
+pub mod day4;
+pub mod day6;
+pub mod intcode;
@@ -1,14 +1,30 @@
-mod day1;
-mod day3;
-mod day4;
-mod day6;
-mod intcode;
-
-use day6::orbital_transfers;
+use adventofcode2019::day6::orbital_transfers;
+use adventofcode2019::intcode::analysis::Analysis;
+use adventofcode2019::intcode::ascii::{play, Ascii};
+use adventofcode2019::intcode::debugger::{repl, Debugger};
+use adventofcode2019::intcode::profiler::Profiler;
+use adventofcode2019::intcode::{
+    disassembler, memory_from_io, Computer, InstructionSet, Memory, State,
+};
+use std::env;
+use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{BufReader, Read};
+use std::sync::{Arc, Mutex};
 
 fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("disassemble") => disassemble(args.get(1)),
+        Some("ascii") => ascii(args.get(1)),
+        Some("cfg") => cfg(args.get(1)),
+        Some("debug") => debug(args.get(1)),
+        Some("profile") => profile(args.get(1), args.get(2..).unwrap_or(&[])),
+        _ => transfers(),
+    }
+}
+
+fn transfers() -> io::Result<()> {
     let mut buffer = String::new();
     io::stdin().lock().read_to_string(&mut buffer)?;
     println!(
@@ -17,3 +33,81 @@ fn main() -> io::Result<()> {
     );
     Ok(())
 }
+
+/// Reads an Intcode program from `path`, or from stdin if no path is given.
+fn read_program(path: Option<&String>) -> io::Result<Memory> {
+    match path {
+        Some(path) => memory_from_io(BufReader::new(File::open(path)?)),
+        None => memory_from_io(io::stdin().lock()),
+    }
+}
+
+fn disassemble(path: Option<&String>) -> io::Result<()> {
+    let memory = read_program(path)?;
+    print!(
+        "{}",
+        disassembler::listing(&InstructionSet::core(), &memory)
+    );
+    Ok(())
+}
+
+/// Prints the control flow graph of a program in the Graphviz DOT language.
+fn cfg(path: Option<&String>) -> io::Result<()> {
+    let memory = read_program(path)?;
+    print!(
+        "{}",
+        Analysis::new(&InstructionSet::core(), &memory).to_dot()
+    );
+    Ok(())
+}
+
+/// Plays a text-based program, sending it each line typed on stdin.
+fn ascii(path: Option<&String>) -> io::Result<()> {
+    let path = path.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The program reads its input from stdin, so it needs a program file",
+        )
+    })?;
+    let mut ascii = Ascii::new(Computer::new(read_program(Some(path))?));
+    play(&mut ascii, io::stdin().lock(), io::stdout())
+}
+
+fn debug(path: Option<&String>) -> io::Result<()> {
+    let path = path.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The debugger reads commands from stdin, so it needs a program file",
+        )
+    })?;
+    let mut debugger = Debugger::new(Computer::new(read_program(Some(path))?));
+    repl(&mut debugger, io::stdin().lock(), io::stdout())
+}
+
+/// Runs a program with the given input values, then prints its output and a profile report.
+fn profile(path: Option<&String>, inputs: &[String]) -> io::Result<()> {
+    let mut computer = Computer::new(read_program(path)?);
+    for input in inputs {
+        let value = input
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Inputs must be integers"))?;
+        computer.push_input(value);
+    }
+    let profiler = Arc::new(Mutex::new(Profiler::new()));
+    computer.set_tracer(Arc::clone(&profiler));
+    let state = computer.run().map_err(io::Error::other)?;
+    if state == State::NeedsInput {
+        println!("Stopped: the program needs more input");
+    }
+
+    println!("Output: {:?}\n", computer.take_outputs());
+    print!(
+        "{}",
+        profiler.lock().expect("Profiler lock is poisoned").report(
+            computer.instruction_set(),
+            &computer.memory,
+            10
+        )
+    );
+    Ok(())
+}